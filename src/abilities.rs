@@ -0,0 +1,289 @@
+use rand::{Rng, StdRng};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Ability {
+    Normal,
+    Fire,
+    Ice,
+    Burning,
+    Wheel,
+    Parasol,
+    Cutter,
+    Beam,
+    Stone,
+    Bomb,
+    Throw,
+    Sleep,
+    Cook,
+    Laser,
+    Ufo,
+    Spark,
+    Tornado,
+    Hammer,
+    Sword,
+    Cupid,
+    Fighter,
+    Magic,
+    Smash,
+    Mini,
+    Crash,
+    Missile,
+    Master
+}
+
+// abilities that a fully random shuffle may hand out; Master is reserved for the final boss fight
+const RANDOM_ABILITY_POOL: [Ability; 25] = [
+    Ability::Fire, Ability::Ice, Ability::Burning, Ability::Wheel, Ability::Parasol,
+    Ability::Cutter, Ability::Beam, Ability::Stone, Ability::Bomb, Ability::Throw,
+    Ability::Sleep, Ability::Cook, Ability::Laser, Ability::Ufo, Ability::Spark,
+    Ability::Tornado, Ability::Hammer, Ability::Sword, Ability::Cupid, Ability::Fighter,
+    Ability::Magic, Ability::Smash, Ability::Mini, Ability::Crash, Ability::Missile
+];
+
+impl FromStr for Ability {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Ability, String> {
+        match name {
+            "normal" => Ok(Ability::Normal),
+            "fire" => Ok(Ability::Fire),
+            "ice" => Ok(Ability::Ice),
+            "burning" => Ok(Ability::Burning),
+            "wheel" => Ok(Ability::Wheel),
+            "parasol" => Ok(Ability::Parasol),
+            "cutter" => Ok(Ability::Cutter),
+            "beam" => Ok(Ability::Beam),
+            "stone" => Ok(Ability::Stone),
+            "bomb" => Ok(Ability::Bomb),
+            "throw" => Ok(Ability::Throw),
+            "sleep" => Ok(Ability::Sleep),
+            "cook" => Ok(Ability::Cook),
+            "laser" => Ok(Ability::Laser),
+            "ufo" => Ok(Ability::Ufo),
+            "spark" => Ok(Ability::Spark),
+            "tornado" => Ok(Ability::Tornado),
+            "hammer" => Ok(Ability::Hammer),
+            "sword" => Ok(Ability::Sword),
+            "cupid" => Ok(Ability::Cupid),
+            "fighter" => Ok(Ability::Fighter),
+            "magic" => Ok(Ability::Magic),
+            "smash" => Ok(Ability::Smash),
+            "mini" => Ok(Ability::Mini),
+            "crash" => Ok(Ability::Crash),
+            "missile" => Ok(Ability::Missile),
+            "master" => Ok(Ability::Master),
+            _ => Err(format!("Unknown ability: {}", name))
+        }
+    }
+}
+
+// the ability together with the byte that grants it in an enemy's spawn data
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct GrantedAbility {
+    pub ability: Ability,
+    pub ability_byte: u8
+}
+
+impl GrantedAbility {
+    pub fn new(ability: Ability, ability_byte: u8) -> GrantedAbility {
+        GrantedAbility {
+            ability,
+            ability_byte
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnemySpawn {
+    pub id: usize,
+    pub room_id: usize,
+    pub ability_addr: usize,
+    pub enemy_type: String,
+    pub granted: GrantedAbility
+}
+
+impl PartialEq for EnemySpawn {
+    fn eq(&self, other: &EnemySpawn) -> bool {
+        self.id == other.id
+    }
+}
+
+// a room that cannot be completed without the given ability
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AbilityGate {
+    pub room_id: usize,
+    pub ability: Ability
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbilityShuffleMode {
+    // every spawn grants any ability from the pool
+    Random,
+    // the vanilla abilities are redistributed, so each ability is granted as often as in the original game
+    PreserveCounts
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AbilityPlacement(pub EnemySpawn, pub GrantedAbility);
+
+pub struct AbilityShuffler {
+    spawns: Vec<EnemySpawn>,
+    gates: Vec<AbilityGate>
+}
+
+impl AbilityShuffler {
+    pub fn new(spawns: Vec<EnemySpawn>, gates: Vec<AbilityGate>) -> AbilityShuffler {
+        AbilityShuffler {
+            spawns,
+            gates
+        }
+    }
+
+    pub fn shuffle_abilities(&self, mode: AbilityShuffleMode, guarantee_gates: bool, rng: &mut StdRng) -> Result<Vec<AbilityPlacement>, String> {
+        let mut abilities: Vec<GrantedAbility> = match mode {
+            AbilityShuffleMode::Random => {
+                let pool = self.random_pool();
+                if pool.is_empty() {
+                    return Err(String::from("No enemy in enemydata.csv grants an ability that can be handed out at random"));
+                }
+                self.spawns.iter()
+                    .map(|_| *rng.choose(&pool).unwrap())
+                    .collect()
+            },
+            AbilityShuffleMode::PreserveCounts => {
+                let mut abilities: Vec<GrantedAbility> = self.spawns.iter().map(|spawn| spawn.granted).collect();
+                rng.shuffle(&mut abilities);
+                abilities
+            }
+        };
+
+        if guarantee_gates {
+            self.satisfy_gates(&mut abilities, mode, rng)?;
+        }
+
+        Ok(self.spawns.iter().cloned().zip(abilities)
+            .map(|(spawn, granted)| AbilityPlacement(spawn, granted))
+            .collect())
+    }
+
+    // the byte of an ability is only known if a documented enemy grants it, so only those can be handed out
+    fn random_pool(&self) -> Vec<GrantedAbility> {
+        let mut pool: Vec<GrantedAbility> = Vec::new();
+        for spawn in &self.spawns {
+            if RANDOM_ABILITY_POOL.contains(&spawn.granted.ability) && !pool.contains(&spawn.granted) {
+                pool.push(spawn.granted);
+            }
+        }
+        pool
+    }
+
+    // make sure that every gated room has an enemy that grants the ability needed to get through it
+    fn satisfy_gates(&self, abilities: &mut [GrantedAbility], mode: AbilityShuffleMode, rng: &mut StdRng) -> Result<(), String> {
+        // spawns that have been assigned to a gate must not be changed again by a later gate
+        let mut locked_indices: Vec<usize> = Vec::new();
+
+        for gate in &self.gates {
+            let room_indices: Vec<usize> = self.find_spawn_indices_in_room(gate.room_id);
+            if let Some(&index) = room_indices.iter().find(|&&index| abilities[index].ability == gate.ability) {
+                locked_indices.push(index);
+                continue;
+            }
+
+            let candidate_indices: Vec<usize> = room_indices.iter()
+                .filter(|&index| !locked_indices.contains(index))
+                .cloned()
+                .collect();
+            let index = *rng.choose(&candidate_indices)
+                .ok_or_else(|| format!("No enemy in room {} is left to grant {:?}", gate.room_id, gate.ability))?;
+
+            match mode {
+                AbilityShuffleMode::Random => {
+                    abilities[index] = *self.spawns.iter()
+                        .map(|spawn| &spawn.granted)
+                        .find(|granted| granted.ability == gate.ability)
+                        .ok_or_else(|| format!("No enemy in enemydata.csv grants {:?}, which room {} needs", gate.ability, gate.room_id))?;
+                },
+                AbilityShuffleMode::PreserveCounts => {
+                    // swap with a spawn elsewhere so that the ability counts stay the same
+                    let swap_indices: Vec<usize> = (0..abilities.len())
+                        .filter(|swap_index| abilities[*swap_index].ability == gate.ability && !locked_indices.contains(swap_index))
+                        .collect();
+                    let swap_index = *rng.choose(&swap_indices)
+                        .ok_or_else(|| format!("No enemy is left to swap {:?} with for room {}", gate.ability, gate.room_id))?;
+                    abilities.swap(index, swap_index);
+                }
+            }
+            locked_indices.push(index);
+        }
+        Ok(())
+    }
+
+    fn find_spawn_indices_in_room(&self, room_id: usize) -> Vec<usize> {
+        self.spawns.iter()
+            .enumerate()
+            .filter(|&(_, spawn)| spawn.room_id == room_id)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn spawn(id: usize, room_id: usize, ability: Ability, ability_byte: u8) -> EnemySpawn {
+        EnemySpawn {
+            id,
+            room_id,
+            ability_addr: id,
+            enemy_type: String::from("test"),
+            granted: GrantedAbility::new(ability, ability_byte)
+        }
+    }
+
+    #[test]
+    fn gates_are_satisfied_and_counts_preserved() {
+        let spawns = vec![
+            spawn(0, 0, Ability::Fire, 1),
+            spawn(1, 0, Ability::Sword, 2),
+            spawn(2, 1, Ability::Stone, 3),
+            spawn(3, 1, Ability::Cutter, 4),
+            spawn(4, 2, Ability::Fire, 1),
+            spawn(5, 2, Ability::Beam, 5)
+        ];
+        let gates = vec![
+            AbilityGate { room_id: 0, ability: Ability::Stone },
+            AbilityGate { room_id: 2, ability: Ability::Fire }
+        ];
+        let ability_shuffler = AbilityShuffler::new(spawns.clone(), gates.clone());
+
+        for seed in 0..50 {
+            let mut rng: StdRng = StdRng::from_seed(&[seed; 32]);
+            let placements = ability_shuffler.shuffle_abilities(AbilityShuffleMode::PreserveCounts, true, &mut rng).unwrap();
+
+            let mut shuffled_abilities: Vec<u8> = placements.iter().map(|placement| placement.1.ability_byte).collect();
+            let mut original_abilities: Vec<u8> = spawns.iter().map(|spawn| spawn.granted.ability_byte).collect();
+            shuffled_abilities.sort();
+            original_abilities.sort();
+            assert_eq!(shuffled_abilities, original_abilities);
+
+            for gate in &gates {
+                assert!(placements.iter().any(|placement| placement.0.room_id == gate.room_id && placement.1.ability == gate.ability));
+            }
+
+            // random abilities are only picked from the documented ones, since their bytes are known
+            let placements = ability_shuffler.shuffle_abilities(AbilityShuffleMode::Random, true, &mut rng).unwrap();
+            assert!(placements.iter().all(|placement| spawns.iter().any(|spawn| spawn.granted == placement.1)));
+        }
+
+        // a gate needing an ability that no enemy grants cannot be satisfied
+        let gates = vec![AbilityGate { room_id: 1, ability: Ability::Hammer }];
+        let ability_shuffler = AbilityShuffler::new(spawns, gates);
+        let mut rng: StdRng = StdRng::from_seed(&[1; 32]);
+        assert!(ability_shuffler.shuffle_abilities(AbilityShuffleMode::Random, true, &mut rng).is_err());
+        assert!(ability_shuffler.shuffle_abilities(AbilityShuffleMode::PreserveCounts, true, &mut rng).is_err());
+    }
+}
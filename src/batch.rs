@@ -0,0 +1,80 @@
+use std::cell::Cell;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+pub fn default_thread_count() -> usize {
+    thread::available_parallelism().map_or(1, |count| count.get())
+}
+
+// runs `generate` for every seed on `thread_count` threads, returning the results ordered by seed. every seed has
+// its own rng made from its number, so the results do not depend on the number of threads
+pub fn generate_parallel<T: Send, F: Fn(u64) -> T + Sync>(seeds: Range<u64>, thread_count: usize, generate: F) -> Vec<(u64, T)> {
+    let next_seed = AtomicU64::new(seeds.start);
+    let results: Mutex<Vec<(u64, T)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..thread_count.max(1) {
+            scope.spawn(|| loop {
+                let seed = next_seed.fetch_add(1, Ordering::Relaxed);
+                if seed >= seeds.end {
+                    break;
+                }
+                let result = generate(seed);
+                results.lock().unwrap().push((seed, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|&(seed, _)| seed);
+    results
+}
+
+thread_local! {
+    // set while a seed is generated by catch_failure on this thread
+    static CATCHING_FAILURE: Cell<bool> = const { Cell::new(false) };
+}
+
+static QUIET_PANIC_HOOK: Once = Once::new();
+
+// the panics of failed seeds are reported separately, so they are not printed. the hook is only installed once and
+// is never swapped out again, so panics on other threads are still printed by the previous hook
+fn install_quiet_panic_hook() {
+    QUIET_PANIC_HOOK.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING_FAILURE.with(|catching| catching.get()) {
+                previous_hook(info);
+            }
+        }));
+    });
+}
+
+// seeds that cannot be generated panic, this turns the panic into its message without printing it
+pub fn catch_failure<T, F: FnOnce() -> T>(generate: F) -> Result<T, String> {
+    install_quiet_panic_hook();
+    let was_catching = CATCHING_FAILURE.with(|catching| catching.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(generate));
+    CATCHING_FAILURE.with(|catching| catching.set(was_catching));
+    result.map_err(|payload| {
+        payload.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("Unknown error"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_do_not_depend_on_threads() {
+        let generate = |seed: u64| catch_failure(|| if seed == 7 { panic!("Seed {} failed", seed) } else { seed * 2 });
+        let results = generate_parallel(5..10, 3, generate);
+        assert_eq!(results, generate_parallel(5..10, 1, generate));
+        assert_eq!(results.iter().map(|&(seed, _)| seed).collect::<Vec<u64>>(), vec![5, 6, 7, 8, 9]);
+        assert_eq!(results[1].1, Ok(12));
+        assert_eq!(results[2].1, Err(String::from("Seed 7 failed")));
+    }
+}
@@ -1,8 +1,11 @@
 use csv;
-use std::u8;
-use std::usize;
-use std::path::Path;
 use super::shuffler::{Room, Destination, Exit, ExitType};
+use super::items::{Chest, Contents, Item};
+use super::abilities::{AbilityGate, EnemySpawn, GrantedAbility};
+use super::enemies::{EnemyCategory, SpawnEntry};
+use super::music::{MusicTrack, TrackKind};
+use super::palette::{PaletteEntry, PaletteKind};
+use super::rom;
 
 pub type DoorTable = Vec<Option<DoorRecord>>;
 
@@ -26,17 +29,14 @@ impl DoorRecord {
     }
 
     pub fn extract_exit(&self) -> Exit {
-        let exit_addr_1 = usize::from_str_radix(self.exitaddr1.as_str(), 16).unwrap();
-        let exit_addr_2 = usize::from_str_radix(self.exitaddr2.as_str(), 16).unwrap();
+        let exit_addr_1 = rom::parse_address(&self.exitaddr1).unwrap();
+        let exit_addr_2 = rom::parse_address(&self.exitaddr2).unwrap();
         let exit_type = if self.isoneway {
             ExitType::OneWay
         } else {
             ExitType::TwoWay
         };
-        let linked_door = match self.linkeddoor {
-            Some(doorid) => doorid,
-            None => -1
-        };
+        let linked_door = self.linkeddoor.unwrap_or(-1);
         Exit::new(self.doorid, exit_addr_1, exit_addr_2, exit_type, linked_door)
     }
 }
@@ -50,13 +50,86 @@ struct RoomRecord {
     twowayexitids: Option<String>
 }
 
+#[derive(Debug, Deserialize)]
+struct ChestRecord {
+    chestid: usize,
+    roomid: usize,
+    itemaddr: String,
+    itembytes: String,
+    item: String,
+    requireditems: Option<String>
+}
+
+impl ChestRecord {
+    fn extract_chest(&self) -> Result<Chest, String> {
+        let item_addr = rom::parse_address(&self.itemaddr)?;
+        let item_bytes: Vec<u8> = parse_bytes(&self.itembytes)?;
+        let item: Item = self.item.parse()?;
+        let required_items: Vec<Item> = match self.requireditems {
+            Some(ref items) => items.split_whitespace().map(|name| name.parse::<Item>()).collect::<Result<Vec<Item>, String>>()?,
+            None => vec![]
+        };
+        Ok(Chest {
+            id: self.chestid,
+            room_id: self.roomid,
+            item_addr,
+            contents: Contents::new(item, item_bytes),
+            required_items
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EnemySpawnRecord {
+    spawnid: usize,
+    roomid: usize,
+    abilityaddr: String,
+    enemytype: String,
+    abilitybyte: String,
+    ability: String
+}
+
+#[derive(Debug, Deserialize)]
+struct AbilityGateRecord {
+    roomid: usize,
+    requiredability: String
+}
+
+#[derive(Debug, Deserialize)]
+struct SpawnEntryRecord {
+    spawnid: usize,
+    roomid: usize,
+    typeaddr: String,
+    enemytype: String,
+    typeid: String,
+    category: String
+}
+
+// the area column only documents which song a row is, the randomizer does not need it
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct MusicTrackRecord {
+    trackid: usize,
+    songaddr: String,
+    songbytes: String,
+    kind: String
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct PaletteRecord {
+    name: String,
+    kind: String,
+    paletteaddr: String
+}
+
 pub struct CsvLoader;
 
 impl CsvLoader {
-    pub fn load_entrances<P: AsRef<Path>>(&self, path: P) -> DoorTable {
+    pub fn load_entrances(&self, contents: &str) -> DoorTable {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
-            .from_path(path).unwrap();
+            .from_reader(contents.as_bytes());
         let records: Vec<DoorRecord> = reader.deserialize()
             .map(|result| result.unwrap())
             .collect();
@@ -66,11 +139,11 @@ impl CsvLoader {
         door_records
     }
 
-    pub fn load_rooms<P: AsRef<Path>>(&self, path: P, door_table: &DoorTable) -> Vec<Room> {
+    pub fn load_rooms(&self, contents: &str, door_table: &DoorTable) -> Result<Vec<Room>, String> {
         let mut rooms = vec![];
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
-            .from_path(path).unwrap();
+            .from_reader(contents.as_bytes());
         for result in reader.deserialize() {
             let record: RoomRecord = result.unwrap();
 
@@ -99,14 +172,118 @@ impl CsvLoader {
             let one_way_exits: Vec<Exit> = one_way_exit_ids.iter().map(|&id| door_table[id].clone().unwrap().extract_exit()).collect();
             let two_way_exits: Vec<Exit> = two_way_exit_ids.iter().map(|&id| door_table[id].clone().unwrap().extract_exit()).collect();
 
-            rooms.push( Room {
-                id: record.roomid,
-                one_way_entrances: one_way_entrances,
-                two_way_entrances: two_way_entrances,
-                one_way_exits: one_way_exits,
-                two_way_exits: two_way_exits
-            });
+            rooms.push(Room::new(record.roomid, one_way_entrances, two_way_entrances, one_way_exits, two_way_exits)?);
         }
-        rooms
+        Ok(rooms)
     }
+
+    pub fn load_chests(&self, contents: &str) -> Result<Vec<Chest>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+        reader.deserialize()
+            .map(|result| {
+                let record: ChestRecord = result.map_err(|error| error.to_string())?;
+                record.extract_chest()
+            })
+            .collect()
+    }
+
+    pub fn load_enemy_spawns(&self, contents: &str) -> Result<Vec<EnemySpawn>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+        reader.deserialize()
+            .map(|result| {
+                let record: EnemySpawnRecord = result.map_err(|error| error.to_string())?;
+                Ok(EnemySpawn {
+                    id: record.spawnid,
+                    room_id: record.roomid,
+                    ability_addr: rom::parse_address(&record.abilityaddr)?,
+                    enemy_type: record.enemytype,
+                    granted: GrantedAbility::new(record.ability.parse()?, parse_byte(&record.abilitybyte)?)
+                })
+            })
+            .collect()
+    }
+
+    pub fn load_ability_gates(&self, contents: &str) -> Result<Vec<AbilityGate>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+        reader.deserialize()
+            .map(|result| {
+                let record: AbilityGateRecord = result.map_err(|error| error.to_string())?;
+                Ok(AbilityGate {
+                    room_id: record.roomid,
+                    ability: record.requiredability.parse()?
+                })
+            })
+            .collect()
+    }
+
+    pub fn load_spawn_entries(&self, contents: &str) -> Result<Vec<SpawnEntry>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+        reader.deserialize()
+            .map(|result| {
+                let record: SpawnEntryRecord = result.map_err(|error| error.to_string())?;
+                Ok(SpawnEntry {
+                    id: record.spawnid,
+                    room_id: record.roomid,
+                    type_addr: rom::parse_address(&record.typeaddr)?,
+                    enemy_type: record.enemytype,
+                    type_id: parse_byte(&record.typeid)?,
+                    category: record.category.parse::<EnemyCategory>()?
+                })
+            })
+            .collect()
+    }
+
+    // not used until musicdata.csv lists the song table, see `mod music`
+    #[allow(dead_code)]
+    pub fn load_music_tracks(&self, contents: &str) -> Result<Vec<MusicTrack>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+        reader.deserialize()
+            .map(|result| {
+                let record: MusicTrackRecord = result.map_err(|error| error.to_string())?;
+                Ok(MusicTrack {
+                    id: record.trackid,
+                    song_addr: rom::parse_address(&record.songaddr)?,
+                    song_bytes: parse_bytes(&record.songbytes)?,
+                    kind: record.kind.parse::<TrackKind>()?
+                })
+            })
+            .collect()
+    }
+
+    // not used until palettedata.csv lists the palettes, see `mod palette`
+    #[allow(dead_code)]
+    pub fn load_palettes(&self, contents: &str) -> Result<Vec<PaletteEntry>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+        reader.deserialize()
+            .map(|result| {
+                let record: PaletteRecord = result.map_err(|error| error.to_string())?;
+                Ok(PaletteEntry {
+                    name: record.name,
+                    kind: record.kind.parse::<PaletteKind>()?,
+                    palette_addr: rom::parse_address(&record.paletteaddr)?
+                })
+            })
+            .collect()
+    }
+}
+
+// bytes are written as hexadecimal pairs separated by spaces, for example "65 00 03 07"
+fn parse_bytes(bytes: &str) -> Result<Vec<u8>, String> {
+    bytes.split_whitespace().map(parse_byte).collect()
+}
+
+fn parse_byte(byte: &str) -> Result<u8, String> {
+    u8::from_str_radix(byte, 16).map_err(|_| format!("Invalid byte: {}", byte))
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::PathBuf;
+use super::world::World;
+
+// the data shipped with the randomizer, so that runners only have to provide their ROM.
+// new area files have to be added here as well as to the world directory
+const DEFAULT_WORLD: [(&str, &str); 9] = [
+    ("area0.toml", include_str!("../world/area0.toml")),
+    ("area1.toml", include_str!("../world/area1.toml")),
+    ("area2.toml", include_str!("../world/area2.toml")),
+    ("area3.toml", include_str!("../world/area3.toml")),
+    ("area4.toml", include_str!("../world/area4.toml")),
+    ("area5.toml", include_str!("../world/area5.toml")),
+    ("area6.toml", include_str!("../world/area6.toml")),
+    ("area7.toml", include_str!("../world/area7.toml")),
+    ("area8.toml", include_str!("../world/area8.toml"))
+];
+
+const DEFAULT_FILES: [(&str, &str); 6] = [
+    ("chestdata.csv", include_str!("../chestdata.csv")),
+    ("enemydata.csv", include_str!("../enemydata.csv")),
+    ("abilitygates.csv", include_str!("../abilitygates.csv")),
+    ("enemyspawndata.csv", include_str!("../enemyspawndata.csv")),
+    ("musicdata.csv", include_str!("../musicdata.csv")),
+    ("palettedata.csv", include_str!("../palettedata.csv"))
+];
+
+// data files are read from the custom data directory when one is given, falling back to the built-in data
+// for any file it does not contain
+pub struct DataFiles {
+    directory: Option<PathBuf>
+}
+
+impl DataFiles {
+    pub fn new(directory: Option<&String>) -> DataFiles {
+        DataFiles {
+            directory: directory.map(PathBuf::from)
+        }
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        if let Some(ref directory) = self.directory {
+            let path = directory.join(name);
+            if path.exists() {
+                return fs::read_to_string(path).unwrap();
+            }
+        }
+        let &(_, contents) = DEFAULT_FILES.iter()
+            .find(|&&(file_name, _)| file_name == name)
+            .unwrap_or_else(|| panic!("Unknown data file: {}", name));
+        contents.to_string()
+    }
+
+    // a custom world replaces the whole built-in world rather than single areas
+    pub fn load_world(&self) -> Result<World, String> {
+        if let Some(ref directory) = self.directory {
+            let world_directory = directory.join("world");
+            if world_directory.exists() {
+                return World::load(world_directory);
+            }
+        }
+        World::from_area_files(&DEFAULT_WORLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_data_loads() {
+        let data = DataFiles::new(None);
+        let world = data.load_world().unwrap();
+        assert_eq!(world.areas.len(), DEFAULT_WORLD.len());
+        assert!(!world.rooms(&world.door_table()).unwrap().is_empty());
+        assert!(data.read("musicdata.csv").starts_with("trackid"));
+    }
+}
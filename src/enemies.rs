@@ -0,0 +1,122 @@
+use rand::{Rng, StdRng};
+use std::str::FromStr;
+
+// enemies are only swapped within the same category so that they cannot end up inside walls or floors
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EnemyCategory {
+    Ground,
+    Flying,
+    Large
+}
+
+impl FromStr for EnemyCategory {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<EnemyCategory, String> {
+        match name {
+            "ground" => Ok(EnemyCategory::Ground),
+            "flying" => Ok(EnemyCategory::Flying),
+            "large" => Ok(EnemyCategory::Large),
+            _ => Err(format!("Unknown enemy category: {}", name))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpawnEntry {
+    pub id: usize,
+    pub room_id: usize,
+    pub type_addr: usize,
+    pub enemy_type: String,
+    pub type_id: u8,
+    pub category: EnemyCategory
+}
+
+impl PartialEq for SpawnEntry {
+    fn eq(&self, other: &SpawnEntry) -> bool {
+        self.id == other.id
+    }
+}
+
+// the spawn entry being written to and the spawn entry whose enemy now appears there
+#[derive(Clone, PartialEq, Debug)]
+pub struct EnemyPlacement(pub SpawnEntry, pub SpawnEntry);
+
+pub struct EnemyShuffler {
+    spawns: Vec<SpawnEntry>
+}
+
+impl EnemyShuffler {
+    pub fn new(spawns: Vec<SpawnEntry>) -> EnemyShuffler {
+        EnemyShuffler {
+            spawns
+        }
+    }
+
+    pub fn shuffle_enemies(&self, rng: &mut StdRng) -> Vec<EnemyPlacement> {
+        let mut placements: Vec<EnemyPlacement> = Vec::new();
+        let mut groups: Vec<(usize, EnemyCategory)> = Vec::new();
+        for spawn in &self.spawns {
+            if !groups.contains(&(spawn.room_id, spawn.category)) {
+                groups.push((spawn.room_id, spawn.category));
+            }
+        }
+
+        for (room_id, category) in groups {
+            let group_spawns: Vec<SpawnEntry> = self.spawns.iter()
+                .filter(|&spawn| spawn.room_id == room_id && spawn.category == category)
+                .cloned()
+                .collect();
+            let mut shuffled_spawns = group_spawns.clone();
+            rng.shuffle(&mut shuffled_spawns);
+            for (spawn, placed_spawn) in group_spawns.into_iter().zip(shuffled_spawns) {
+                placements.push(EnemyPlacement(spawn, placed_spawn));
+            }
+        }
+
+        placements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn spawn(id: usize, room_id: usize, category: EnemyCategory) -> SpawnEntry {
+        SpawnEntry {
+            id,
+            room_id,
+            type_addr: id,
+            enemy_type: format!("enemy {}", id),
+            type_id: id as u8,
+            category
+        }
+    }
+
+    #[test]
+    fn enemies_stay_in_room_and_category() {
+        let spawns = vec![
+            spawn(0, 0, EnemyCategory::Ground),
+            spawn(1, 0, EnemyCategory::Flying),
+            spawn(2, 0, EnemyCategory::Ground),
+            spawn(3, 1, EnemyCategory::Ground),
+            spawn(4, 1, EnemyCategory::Large),
+            spawn(5, 1, EnemyCategory::Ground)
+        ];
+        let enemy_shuffler = EnemyShuffler::new(spawns.clone());
+
+        for seed in 0..20 {
+            let mut rng: StdRng = StdRng::from_seed(&[seed; 32]);
+            let placements = enemy_shuffler.shuffle_enemies(&mut rng);
+            assert_eq!(placements.len(), spawns.len());
+            for spawn in &spawns {
+                assert_eq!(placements.iter().filter(|placement| placement.1 == *spawn).count(), 1);
+            }
+            for EnemyPlacement(spawn, placed_spawn) in &placements {
+                assert_eq!(spawn.room_id, placed_spawn.room_id);
+                assert_eq!(spawn.category, placed_spawn.category);
+            }
+        }
+    }
+}
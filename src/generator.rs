@@ -0,0 +1,241 @@
+use rand::{Rng, StdRng};
+use bidir_map::BidirMap;
+use super::shuffler::{Door, Destination, Exit, ExitType, Shuffler, Room};
+use super::items::{ItemPlacement, ItemShuffler};
+use super::abilities::{AbilityPlacement, AbilityShuffler};
+use super::enemies::{EnemyPlacement, EnemyShuffler};
+use super::csv_loader::{CsvLoader, DoorTable};
+use super::data::DataFiles;
+use super::graph::WorldGraph;
+use super::plando;
+use super::rom::{self, Rom};
+use super::seed;
+use super::selection;
+use super::settings::Settings;
+use super::world::World;
+
+// everything needed to generate seeds with one set of settings. the data is loaded once, so many seeds can be
+// generated from it, also from several threads at a time
+pub struct Generator<'a> {
+    settings: &'a Settings,
+    rooms: Vec<Room>,
+    shuffler: Shuffler,
+    // the rooms left after placing the pinned doors, which are the same for every seed
+    shuffled_rooms: Vec<Room>,
+    pinned_doors: Vec<Door>,
+    // the room of `shuffled_rooms` that a new file starts in and the entrance it is entered through, unless the
+    // start is random
+    vanilla_start: Option<(usize, Destination)>,
+    settings_string: String,
+    item_shuffler: Option<ItemShuffler>,
+    ability_shuffler: Option<AbilityShuffler>,
+    enemy_shuffler: Option<EnemyShuffler>
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(loader: &CsvLoader, data: &DataFiles, world: &World, settings: &'a Settings) -> Result<Generator<'a>, String> {
+        let door_table = world.door_table();
+        let rooms = world.rooms(&door_table)?;
+        let shuffler = build_shuffler(&door_table);
+
+        // excluded doors are pinned first, so a plando connection using one of them is reported as a conflict.
+        // pins do not depend on the seed, so mistakes in them are reported here instead of failing every seed
+        let mut connections = plando::excluded_connections(&settings.excluded_doors, &settings.excluded_rooms, &rooms)?;
+        connections.extend(settings.plando.iter().cloned());
+        let pins = plando::to_doors(&connections, &door_table)?;
+        // a new file always starts at the vanilla spawn entrance unless the start is random, so it cannot be pinned
+        let vanilla_spawn_entrance = if settings.random_start {
+            None
+        } else {
+            Some(shuffler.find_vanilla_spawn_entrance(spawn_exit())?)
+        };
+        if let Some(&Door(destination, exit)) = pins.iter().find(|&&Door(destination, _)| Some(destination) == vanilla_spawn_entrance) {
+            return Err(format!("Door {} cannot lead to destination {}, which is where a new file starts", exit.id, destination.id));
+        }
+        let (shuffled_rooms, pinned_doors) = shuffler.place_pinned_doors(&pins, &rooms)?;
+        let vanilla_start = match vanilla_spawn_entrance {
+            Some(spawn_entrance) => {
+                let index = shuffled_rooms.iter()
+                    .position(|room| room.one_way_entrances.contains(&spawn_entrance))
+                    .ok_or_else(|| format!("Destination {}, where a new file starts, is not in a room", spawn_entrance.id))?;
+                Some((index, spawn_entrance))
+            },
+            None => None
+        };
+
+        // the other data files are only read when their option is on, like before the generator existed. an option
+        // whose data file lists nothing would not change the seed, so it is rejected
+        let item_shuffler = if settings.shuffle_items {
+            let chests = loader.load_chests(&data.read("chestdata.csv")).map_err(|error| format!("chestdata.csv: {}", error))?;
+            if chests.is_empty() {
+                return Err(String::from("No chests are listed in chestdata.csv, so the items cannot be shuffled"));
+            }
+            Some(ItemShuffler::new(chests))
+        } else {
+            None
+        };
+        if settings.guarantee_abilities && settings.ability_shuffle.is_none() {
+            return Err(String::from("--guarantee-abilities only works together with --shuffle-abilities or --random-abilities"));
+        }
+        let ability_shuffler = if settings.ability_shuffle.is_some() {
+            let spawns = loader.load_enemy_spawns(&data.read("enemydata.csv")).map_err(|error| format!("enemydata.csv: {}", error))?;
+            let gates = loader.load_ability_gates(&data.read("abilitygates.csv")).map_err(|error| format!("abilitygates.csv: {}", error))?;
+            if spawns.is_empty() {
+                return Err(String::from("No enemies are listed in enemydata.csv, so their abilities cannot be shuffled"));
+            }
+            if settings.guarantee_abilities && gates.is_empty() {
+                return Err(String::from("No rooms are listed in abilitygates.csv, so no abilities can be guaranteed"));
+            }
+            Some(AbilityShuffler::new(spawns, gates))
+        } else {
+            None
+        };
+        let enemy_shuffler = if settings.shuffle_enemies {
+            let spawns = loader.load_spawn_entries(&data.read("enemyspawndata.csv")).map_err(|error| format!("enemyspawndata.csv: {}", error))?;
+            if spawns.is_empty() {
+                return Err(String::from("No enemy spawns are listed in enemyspawndata.csv, so the enemies cannot be shuffled"));
+            }
+            Some(EnemyShuffler::new(spawns))
+        } else {
+            None
+        };
+
+        Ok(Generator {
+            settings,
+            rooms,
+            shuffler,
+            shuffled_rooms,
+            pinned_doors,
+            vanilla_start,
+            settings_string: settings.to_settings_string()?,
+            item_shuffler,
+            ability_shuffler,
+            enemy_shuffler
+        })
+    }
+
+    // apply every setting to a copy of the clean ROM, returning it with the spoiler and the shuffled world
+    pub fn randomize(&self, clean_rom: &Rom, seed: u64, race_secret: Option<u64>) -> Result<(Rom, Vec<String>, WorldGraph), String> {
+        let mut game_rom = clean_rom.clone();
+        let mut spoiler: Vec<String> = Vec::new();
+
+        let mut rng: StdRng = seed::rng_from_seed(seed, race_secret);
+        let (result, spawn_entrance) = self.shuffle_doors(&mut rng)?;
+        spoiler.push(format!("{}", result.len()));
+
+        for door in &result {
+            let &Door(destination, exit) = door;
+
+            let exit_addr1 = exit.exit_addr1;
+            let exit_addr2 = exit.exit_addr2;
+            spoiler.push(format!("{:x} | {:x}", exit_addr1, exit_addr2));
+
+            let exit_room = self.rooms.iter()
+                .find(|&room| room.one_way_exits.contains(&exit) || room.two_way_exits.contains(&exit))
+                .expect("Could not find room for exit");
+            let destination_data = destination.destination_bytes;
+            spoiler.push(format!("{} -> {}", self.describe_room(exit_room.id), destination.data()));
+
+            spoiler.push(String::new());
+            game_rom.write_bytes(&destination_data[..], exit_addr1);
+            game_rom.write_bytes(&destination_data[..], exit_addr2);
+        }
+
+        game_rom.write_spawn_destination(&spawn_entrance.destination_bytes[..]);
+        let world_graph = self.world_graph(&result, spawn_entrance);
+
+        if let Some(ref item_shuffler) = self.item_shuffler {
+            for placement in item_shuffler.shuffle_items(&mut rng) {
+                let ItemPlacement(chest, contents) = placement;
+                spoiler.push(format!("chest {} ({}) | {:?}", chest.id, self.describe_room(chest.room_id), contents.item));
+                game_rom.write_bytes(&contents.item_bytes[..], chest.item_addr);
+            }
+        }
+
+        if let (Some(ref ability_shuffler), Some(mode)) = (&self.ability_shuffler, self.settings.ability_shuffle) {
+            for placement in ability_shuffler.shuffle_abilities(mode, self.settings.guarantee_abilities, &mut rng)? {
+                let AbilityPlacement(spawn, granted) = placement;
+                spoiler.push(format!("enemy {} {} ({}) | {:?}", spawn.id, spawn.enemy_type, self.describe_room(spawn.room_id), granted.ability));
+                game_rom.write_byte(granted.ability_byte, spawn.ability_addr);
+            }
+        }
+
+        if let Some(ref enemy_shuffler) = self.enemy_shuffler {
+            for placement in enemy_shuffler.shuffle_enemies(&mut rng) {
+                let EnemyPlacement(spawn, placed_spawn) = placement;
+                spoiler.push(format!("spawn {} ({}) | {}", spawn.id, self.describe_room(spawn.room_id), placed_spawn.enemy_type));
+                game_rom.write_byte(placed_spawn.type_id, spawn.type_addr);
+            }
+        }
+
+        // the seed itself is never written to the ROM, only the hash
+        game_rom.write_seed_hash(&seed::seed_hash(seed, &self.settings_string, race_secret));
+        Ok((game_rom, spoiler, world_graph))
+    }
+
+    // place the pinned doors and shuffle the rest, returning every door placed and the entrance the new-file spawn leads to
+    pub fn shuffle_doors(&self, rng: &mut StdRng) -> Result<(Vec<Door>, Destination), String> {
+        let (start_room, spawn_entrance): (&Room, Destination) = match self.vanilla_start {
+            Some((index, spawn_entrance)) => (&self.shuffled_rooms[index], spawn_entrance),
+            None => {
+                let start_rooms = self.shuffler.find_eligible_start_rooms(&self.shuffled_rooms);
+                let start_room = *rng.choose(&start_rooms).ok_or_else(|| String::from("No room can be used as the start room"))?;
+                (start_room, *rng.choose(&start_room.one_way_entrances).expect("Could not find spawn entrance"))
+            }
+        };
+        let strategy = selection::strategy(self.settings.selection_mode);
+        let mut doors = self.pinned_doors.clone();
+        doors.extend(self.shuffler.shuffle_rooms(start_room, spawn_entrance, &self.shuffled_rooms, &*strategy, self.settings.dead_end_placement, rng)?);
+        Ok((doors, spawn_entrance))
+    }
+
+    // the shuffled world including the new-file spawn, which leads out of the spawn room
+    pub fn world_graph(&self, doors: &[Door], spawn_entrance: Destination) -> WorldGraph {
+        let mut world_doors = doors.to_vec();
+        world_doors.push(Door(spawn_entrance, spawn_exit()));
+        WorldGraph::new(&self.rooms, &world_doors)
+    }
+
+    // the room of the world data that the new-file spawn leads into
+    pub fn start_room(&self, spawn_entrance: Destination) -> usize {
+        self.rooms.iter()
+            .find(|&room| room.one_way_entrances.contains(&spawn_entrance))
+            .expect("Could not find start room")
+            .id
+    }
+
+    // spoilers use the game's room numbers, falling back to the world data id for rooms without one
+    fn describe_room(&self, room_id: usize) -> String {
+        match self.rooms.iter().find(|&room| room.id == room_id).and_then(|room| room.game_room) {
+            Some(game_room) => format!("room {}", game_room),
+            None => format!("world room {}", room_id)
+        }
+    }
+}
+
+// the door links of the unmodified game, which the shuffler uses to keep two-way doors paired up
+fn build_shuffler(door_table: &DoorTable) -> Shuffler {
+    let mut original_destination_exit_map: BidirMap<Destination, Exit> = BidirMap::new();
+    let mut original_links: BidirMap<Door, Door> = BidirMap::new();
+    for option_record in door_table {
+        if let Some(ref record) = *option_record {
+            let destination = record.extract_destination();
+            let exit = record.extract_exit();
+            original_destination_exit_map.insert(destination, exit);
+
+            if exit.exit_type == ExitType::TwoWay {
+                let option_linked_record = &door_table[exit.linked_door_id as usize];
+                if let Some(ref linked_record) = *option_linked_record {
+                    let linked_destination = linked_record.extract_destination();
+                    let linked_exit = linked_record.extract_exit();
+                    original_links.insert(Door(destination, exit), Door(linked_destination, linked_exit));
+                }
+            }
+        }
+    }
+    Shuffler::new(original_destination_exit_map, original_links)
+}
+
+fn spawn_exit() -> Exit {
+    Exit::new(0, rom::SPAWN_ADDR1, rom::SPAWN_ADDR2, ExitType::OneWay, -1)
+}
@@ -0,0 +1,183 @@
+use serde_json;
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use super::shuffler::{Door, ExitType, Room};
+use super::world;
+
+// one color per area, see `world::area_of`
+const AREA_COLORS: [&str; 10] = [
+    "gray", "lightpink", "orange", "gold", "palegreen", "cyan", "lightblue", "plum", "tan", "salmon"
+];
+
+// a door leading out of a room
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct GraphEdge {
+    pub door: usize,
+    pub destination: usize,
+    pub to_room: usize,
+    pub one_way: bool
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct GraphNode {
+    pub room: usize,
+    pub game_room: Option<u16>,
+    pub exits: Vec<GraphEdge>
+}
+
+impl GraphNode {
+    fn area(&self) -> u16 {
+        world::area_of(self.game_room)
+    }
+}
+
+// the rooms of the world as an adjacency list, built from the doors that connect them
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct WorldGraph {
+    pub rooms: Vec<GraphNode>,
+    // doors whose exit or destination is not listed in any room
+    pub unplaced_doors: Vec<usize>
+}
+
+impl WorldGraph {
+    pub fn new(rooms: &[Room], doors: &[Door]) -> WorldGraph {
+        let mut exit_rooms: HashMap<usize, usize> = HashMap::new();
+        let mut destination_rooms: HashMap<usize, usize> = HashMap::new();
+        for room in rooms {
+            for exit in room.one_way_exits.iter().chain(room.two_way_exits.iter()) {
+                exit_rooms.insert(exit.id, room.id);
+            }
+            for destination in room.one_way_entrances.iter().chain(room.two_way_entrances.iter()) {
+                destination_rooms.insert(destination.id, room.id);
+            }
+        }
+
+        let mut nodes: Vec<GraphNode> = rooms.iter()
+            .map(|room| GraphNode {
+                room: room.id,
+                game_room: room.game_room,
+                exits: Vec::new()
+            })
+            .collect();
+
+        let mut unplaced_doors: Vec<usize> = Vec::new();
+        for &Door(destination, exit) in doors {
+            let (from_room, to_room) = match (exit_rooms.get(&exit.id), destination_rooms.get(&destination.id)) {
+                (Some(&from_room), Some(&to_room)) => (from_room, to_room),
+                _ => {
+                    unplaced_doors.push(exit.id);
+                    continue;
+                }
+            };
+            let node = nodes.iter_mut().find(|node| node.room == from_room).unwrap();
+            node.exits.push(GraphEdge {
+                door: exit.id,
+                destination: destination.id,
+                to_room,
+                one_way: exit.exit_type == ExitType::OneWay
+            });
+        }
+
+        WorldGraph {
+            rooms: nodes,
+            unplaced_doors
+        }
+    }
+
+    // one-way doors are dashed and two-way doors are solid; rooms are colored by area
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph world {\n    node [style=filled];\n");
+        for node in &self.rooms {
+            let label = match node.game_room {
+                Some(game_room) => format!("{} ({})", node.room, game_room),
+                None => node.room.to_string()
+            };
+            let color = AREA_COLORS[node.area() as usize % AREA_COLORS.len()];
+            dot.push_str(&format!("    {} [label=\"{}\", fillcolor={}];\n", node.room, label, color));
+        }
+        for node in &self.rooms {
+            for edge in &node.exits {
+                let style = if edge.one_way { "dashed" } else { "solid" };
+                dot.push_str(&format!("    {} -> {} [style={}];\n", node.room, edge.to_room, style));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // breadth-first search from `start`, returning the doors on a shortest path to every room that can be reached
+    pub fn shortest_paths(&self, start: usize) -> HashMap<usize, Vec<usize>> {
+        let mut paths: HashMap<usize, Vec<usize>> = HashMap::new();
+        paths.insert(start, Vec::new());
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        queue.push_back(start);
+        while let Some(room) = queue.pop_front() {
+            let path = paths[&room].clone();
+            let node = match self.rooms.iter().find(|node| node.room == room) {
+                Some(node) => node,
+                None => continue
+            };
+            for edge in &node.exits {
+                if let Entry::Vacant(entry) = paths.entry(edge.to_room) {
+                    let mut new_path = path.clone();
+                    new_path.push(edge.door);
+                    entry.insert(new_path);
+                    queue.push_back(edge.to_room);
+                }
+            }
+        }
+        paths
+    }
+
+    // rooms that are only entered from one other room and can only be left back into it
+    pub fn dead_ends(&self) -> Vec<usize> {
+        self.rooms.iter()
+            .filter(|node| {
+                let entered_from: Vec<usize> = self.rooms.iter()
+                    .filter(|other| other.exits.iter().any(|edge| edge.to_room == node.room))
+                    .map(|other| other.room)
+                    .collect();
+                entered_from.len() == 1 && entered_from[0] != node.room && node.exits.iter().all(|edge| edge.to_room == entered_from[0])
+            })
+            .map(|node| node.room)
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::shuffler::{Destination, Exit};
+
+    #[test]
+    fn doors_become_edges() {
+        let spawn = Door(Destination::new(0, [0x65, 0x00, 0x03, 0x07]), Exit::new(0, 0, 0, ExitType::OneWay, -1));
+        let link = Door(Destination::new(1, [0xC9, 0x00, 0x01, 0x01]), Exit::new(2, 0, 0, ExitType::TwoWay, 1));
+        let rooms = vec![
+            Room { id: 0, game_room: None, one_way_entrances: vec![], two_way_entrances: vec![], one_way_exits: vec![spawn.1], two_way_exits: vec![] },
+            Room { id: 1, game_room: Some(101), one_way_entrances: vec![spawn.0], two_way_entrances: vec![], one_way_exits: vec![], two_way_exits: vec![link.1] },
+            Room { id: 2, game_room: Some(201), one_way_entrances: vec![], two_way_entrances: vec![link.0], one_way_exits: vec![], two_way_exits: vec![] }
+        ];
+        let unplaced = Door(Destination::new(3, [0x66, 0x00, 0x00, 0x00]), Exit::new(3, 0, 0, ExitType::OneWay, -1));
+        let graph = WorldGraph::new(&rooms, &[spawn, link, unplaced]);
+
+        assert_eq!(graph.rooms[0].game_room, None);
+        assert_eq!(graph.rooms[1].game_room, Some(101));
+        assert_eq!(graph.rooms[2].area(), 2);
+        assert_eq!(graph.rooms[0].exits, vec![GraphEdge { door: 0, destination: 0, to_room: 1, one_way: true }]);
+        assert_eq!(graph.rooms[1].exits, vec![GraphEdge { door: 2, destination: 1, to_room: 2, one_way: false }]);
+        assert_eq!(graph.unplaced_doors, vec![3]);
+
+        assert_eq!(graph.shortest_paths(0)[&2], vec![0, 2]);
+        assert!(!graph.shortest_paths(1).contains_key(&0));
+        assert_eq!(graph.dead_ends(), vec![2]);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("    0 -> 1 [style=dashed];"));
+        assert!(dot.contains("    1 -> 2 [style=solid];"));
+    }
+}
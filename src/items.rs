@@ -0,0 +1,157 @@
+use rand::{Rng, StdRng};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Item {
+    VitalityHeart,
+    CellPhoneBattery,
+    AreaMap,
+    SprayPaint,
+    SoundPlayer,
+    SmashPiece
+}
+
+impl FromStr for Item {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Item, String> {
+        match name {
+            "vitalityheart" => Ok(Item::VitalityHeart),
+            "cellphonebattery" => Ok(Item::CellPhoneBattery),
+            "areamap" => Ok(Item::AreaMap),
+            "spraypaint" => Ok(Item::SprayPaint),
+            "soundplayer" => Ok(Item::SoundPlayer),
+            "smashpiece" => Ok(Item::SmashPiece),
+            _ => Err(format!("Unknown item: {}", name))
+        }
+    }
+}
+
+// the item together with the bytes that represent it in a chest
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Contents {
+    pub item: Item,
+    pub item_bytes: Vec<u8>
+}
+
+impl Contents {
+    pub fn new(item: Item, item_bytes: Vec<u8>) -> Contents {
+        Contents {
+            item,
+            item_bytes
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Chest {
+    pub id: usize,
+    pub room_id: usize,
+    pub item_addr: usize,
+    pub contents: Contents,
+    // items the player needs before the chest can be opened
+    pub required_items: Vec<Item>
+}
+
+impl PartialEq for Chest {
+    fn eq(&self, other: &Chest) -> bool {
+        self.id == other.id
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ItemPlacement(pub Chest, pub Contents);
+
+pub struct ItemShuffler {
+    chests: Vec<Chest>
+}
+
+impl ItemShuffler {
+    pub fn new(chests: Vec<Chest>) -> ItemShuffler {
+        ItemShuffler {
+            chests
+        }
+    }
+
+    pub fn shuffle_items(&self, rng: &mut StdRng) -> Vec<ItemPlacement> {
+        let mut pool: Vec<Contents> = self.chests.iter().map(|chest| chest.contents.clone()).collect();
+        rng.shuffle(&mut pool);
+
+        let (mut progression_items, other_items): (Vec<Contents>, Vec<Contents>) = pool.into_iter()
+            .partition(|contents| self.is_progression_item(contents.item));
+        let mut empty_chests: Vec<Chest> = self.chests.clone();
+        let mut placements: Vec<ItemPlacement> = Vec::new();
+
+        // assumed fill: every progression item goes into a chest that can be opened with the progression items
+        // that have not been placed yet, so collecting them in reverse placement order always works out
+        while let Some(contents) = progression_items.pop() {
+            let assumed_items: Vec<Item> = progression_items.iter().map(|contents| contents.item).collect();
+            let reachable_chests: Vec<Chest> = empty_chests.iter()
+                .filter(|&chest| self.chest_is_reachable(chest, &assumed_items))
+                .cloned()
+                .collect();
+            let chest = rng.choose(&reachable_chests).expect("Could not find reachable chest").clone();
+            empty_chests.retain(|empty_chest| *empty_chest != chest);
+            placements.push(ItemPlacement(chest, contents));
+        }
+
+        assert_eq!(empty_chests.len(), other_items.len());
+        for (chest, contents) in empty_chests.into_iter().zip(other_items) {
+            placements.push(ItemPlacement(chest, contents));
+        }
+
+        placements
+    }
+
+    fn is_progression_item(&self, item: Item) -> bool {
+        self.chests.iter().any(|chest| chest.required_items.contains(&item))
+    }
+
+    fn chest_is_reachable(&self, chest: &Chest, collected_items: &[Item]) -> bool {
+        chest.required_items.iter().all(|item| collected_items.contains(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn chest(id: usize, item: Item, required_items: Vec<Item>) -> Chest {
+        Chest {
+            id,
+            room_id: id,
+            item_addr: id,
+            contents: Contents::new(item, vec![id as u8]),
+            required_items
+        }
+    }
+
+    #[test]
+    fn progression_items_are_reachable() {
+        let chests = vec![
+            chest(0, Item::AreaMap, vec![]),
+            chest(1, Item::SprayPaint, vec![]),
+            chest(2, Item::SmashPiece, vec![Item::SoundPlayer]),
+            chest(3, Item::SoundPlayer, vec![Item::VitalityHeart]),
+            chest(4, Item::VitalityHeart, vec![]),
+            chest(5, Item::CellPhoneBattery, vec![Item::SoundPlayer, Item::VitalityHeart])
+        ];
+        let item_shuffler = ItemShuffler::new(chests.clone());
+
+        for seed in 0..50 {
+            let mut rng: StdRng = StdRng::from_seed(&[seed; 32]);
+            let mut placements = item_shuffler.shuffle_items(&mut rng);
+            assert_eq!(placements.len(), chests.len());
+
+            let mut collected_items: Vec<Item> = Vec::new();
+            while !placements.is_empty() {
+                let reachable_index = placements.iter()
+                    .position(|ItemPlacement(chest, _)| item_shuffler.chest_is_reachable(chest, &collected_items))
+                    .expect("Chest is locked");
+                let ItemPlacement(_, contents) = placements.remove(reachable_index);
+                collected_items.push(contents.item);
+            }
+        }
+    }
+}
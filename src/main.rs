@@ -4,74 +4,316 @@ extern crate csv;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
+extern crate toml;
+extern crate base64;
+extern crate serde_json;
 
 mod shuffler;
 mod csv_loader;
 mod rom;
+mod items;
+mod abilities;
+mod enemies;
+// music and palettes are not shuffled until musicdata.csv and palettedata.csv list the song table and the palettes
+#[allow(dead_code)]
+mod music;
+#[allow(dead_code)]
+mod palette;
+mod settings;
+mod seed;
+mod graph;
+mod validation;
+mod world;
+mod data;
+mod plando;
+mod selection;
+mod stats;
+mod generator;
+mod batch;
 
-use rand::{StdRng, SeedableRng};
-use bidir_map::BidirMap;
-use std::fs::File;
-use shuffler::{Door, Destination, Exit, ExitType, Shuffler, Room};
+use rand::StdRng;
+use std::env;
+use std::fs::{self, File};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+use shuffler::Door;
+use csv_loader::CsvLoader;
+use rom::Rom;
+use settings::Settings;
+use graph::WorldGraph;
+use world::World;
+use data::DataFiles;
+use stats::{SeedStats, Statistics};
+use generator::Generator;
+
+const RACE_SECRET_PATH: &str = "Randomized Kirby and the Amazing Mirror Race Secret.txt";
+const WORLD_DIRECTORY: &str = "world";
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let settings = exit_on_error(Settings::from_args(&args));
+    let loader = CsvLoader;
+    let data = DataFiles::new(settings::find_arg_value(&args, "--data"));
+
+    if let Some(path) = settings::find_arg_value(&args, "--save-settings") {
+        settings.save(path);
+    }
+
+    // data documented in the old doordata.csv and roomdata.csv format can be turned into a world definition
+    if args.len() > 1 && args[1] == "convert-csv" {
+        let door_table = loader.load_entrances(&fs::read_to_string("doordata.csv").unwrap());
+        let rooms = exit_on_error(loader.load_rooms(&fs::read_to_string("roomdata.csv").unwrap(), &door_table));
+        let directory = settings::find_arg_value(&args, "--world").map_or(WORLD_DIRECTORY, |directory| directory.as_str());
+        World::from_csv(&door_table, &rooms).save(directory);
+        return;
+    }
+
+    // older world data is migrated whenever it is loaded, this writes it back in the current version
+    if args.len() > 2 && args[1] == "migrate-world" {
+        exit_on_error(World::load(&args[2])).save(&args[2]);
+        return;
+    }
+
+    let mut world = exit_on_error(data.load_world());
+
+    // the vanilla graph only needs the world definition, so contributors can check it without a ROM
+    if args.len() > 1 && args[1] == "graph" {
+        let door_table = world.door_table();
+        let rooms = exit_on_error(world.rooms(&door_table));
+        let doors: Vec<Door> = door_table.iter()
+            .filter_map(|option_record| option_record.as_ref())
+            .map(|record| Door(record.extract_destination(), record.extract_exit()))
+            .collect();
+        let world_graph = WorldGraph::new(&rooms, &doors);
+        for door_id in &world_graph.unplaced_doors {
+            println!("Door {} is not placed in a room", door_id);
+        }
+        export_graph(&args, &world_graph);
+        return;
+    }
+
+    // only the doors are shuffled, so the stats do not need a ROM either. seeds are numbered from --seed,
+    // so the same seeds can be compared before and after a change to the shuffler
+    if args.len() > 2 && args[1] == "stats" {
+        let seeds = seed_range(&args);
+        let generator = exit_on_error(Generator::new(&loader, &data, &world, &settings));
+        let boss_rooms = world.boss_rooms();
+        let hub_rooms = world.hub_rooms();
+        let mut statistics = Statistics::new(boss_rooms.clone(), hub_rooms.clone());
+
+        // failed seeds are counted instead of stopping the run
+        let results = batch::generate_parallel(seeds, thread_count(&args), |seed| batch::catch_failure(|| {
+            let mut rng: StdRng = seed::rng_from_seed(seed, None);
+            let (doors, spawn_entrance) = generator.shuffle_doors(&mut rng)?;
+            let world_graph = generator.world_graph(&doors, spawn_entrance);
+            Ok(SeedStats::new(&world_graph, generator.start_room(spawn_entrance), &boss_rooms, &hub_rooms))
+        }).and_then(|result| result));
+        for (_, result) in results {
+            match result {
+                Ok(seed_stats) => statistics.add(seed_stats),
+                Err(message) => statistics.add_failure(message)
+            }
+        }
+
+        for line in statistics.report() {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    // times the door shuffle on one thread, which is the part of generation that grows with the size of the world
+    if args.len() > 2 && args[1] == "bench" {
+        let seeds = seed_range(&args);
+        let seed_count = seeds.end - seeds.start;
+        let generator = exit_on_error(Generator::new(&loader, &data, &world, &settings));
+        let start = Instant::now();
+        let failures = seeds
+            .filter(|&seed| batch::catch_failure(|| generator.shuffle_doors(&mut seed::rng_from_seed(seed, None))).and_then(|result| result).is_err())
+            .count();
+        let elapsed = start.elapsed();
+        println!("Shuffled {} seeds in {:.2?} ({:.2?} per seed), {} failed", seed_count, elapsed, elapsed / seed_count.max(1) as u32, failures);
+        return;
+    }
 
     // TODO: ensure that the user provides the ROM!
     let file = File::open("Kirby & The Amazing Mirror (U).gba").unwrap();
-    let mut game_rom = rom::Rom::new(file);
-
-    let loader = csv_loader::CsvLoader;
-    let door_table = loader.load_entrances("doordata.csv");
-    let rooms = loader.load_rooms("roomdata.csv", &door_table);
-
-    let mut original_destination_exit_map: BidirMap<Destination, Exit> = BidirMap::new();
-    let mut original_links: BidirMap<Door, Door> = BidirMap::new();
-    for option_record in &door_table {
-        if let Some(ref record) = *option_record {
-            let destination = record.extract_destination();
-            let exit = record.extract_exit();
-            original_destination_exit_map.insert(destination, exit);
-
-            match exit.exit_type {
-                ExitType::TwoWay => {
-                    let option_linked_record = &door_table[exit.linked_door_id as usize];
-                    if let Some(ref linked_record) = *option_linked_record {
-                        let linked_destination = linked_record.extract_destination();
-                        let linked_exit = linked_record.extract_exit();
-                        original_links.insert(Door(destination, exit), Door(linked_destination, linked_exit));
-                    }
-                }
-                _ => ()
+    let clean_rom = Rom::new(file);
+
+    // a quick look at the ROM for documenting new data, the address can be a ROM offset or a cartridge pointer
+    if args.len() > 2 && args[1] == "read" {
+        let address = usize::from_str_radix(args[2].trim_start_matches("0x"), 16).expect("Address must be hexadecimal");
+        let address = rom::resolve_pointer(address).unwrap_or(address);
+        let length: usize = args.get(3).map_or(4, |length| length.parse().expect("Length must be a number"));
+        println!("{:X}: {}", address, rom::format_bytes(clean_rom.read_bytes(address, length)));
+        println!("u16: {:X}, u32: {:X}", clean_rom.read_u16(address), clean_rom.read_u32(address));
+        if let Some(target) = clean_rom.read_pointer(address) {
+            println!("Pointer to {:X}", target);
+        }
+        return;
+    }
+
+    // door destinations are copied by hand, so check them against the bytes at each exit
+    if args.len() > 1 && args[1] == "extract-doors" {
+        let mismatches = validation::find_destination_mismatches(&world.door_table(), &clean_rom);
+        for mismatch in &mismatches {
+            println!("{}", mismatch);
+            // a door whose exits disagree has to be looked at by hand
+            if mismatch.exits_agree() {
+                let bytes = &mismatch.exit_bytes1;
+                world.set_destination_bytes(mismatch.door_id, [bytes[0], bytes[1], bytes[2], bytes[3]]);
+            } else {
+                println!("Door {}: exitaddr1 and exitaddr2 disagree, not corrected", mismatch.door_id);
             }
         }
+        println!("{} destinations differ from the ROM", mismatches.len());
+
+        if let Some(directory) = settings::find_arg_value(&args, "--write") {
+            world.save(directory);
+        }
+        return;
     }
 
-    let mut rng: StdRng = StdRng::from_seed(&[1usize; 32]);
-    let first_room: Room = Room {
-        id: 0,
-        one_way_entrances: Vec::new(),
-        two_way_entrances: Vec::new(),
-        one_way_exits: vec![Exit::new(0, 0x873450, 0x930E04, ExitType::OneWay, -1)],
-        two_way_exits: Vec::new()
+    // organizers can check a race ROM by regenerating it with the seed, settings and race secret
+    if args.len() > 2 && args[1] == "verify" {
+        let seed: u64 = settings::find_arg_value(&args, "--seed").expect("Missing --seed").parse().expect("Seed must be a number");
+        let race_secret: Option<u64> = settings::find_arg_value(&args, "--race-secret")
+            .map(|secret| secret.parse().expect("Race secret must be a number"));
+        let submitted_rom = Rom::new(File::open(&args[2]).unwrap());
+        let generator = exit_on_error(Generator::new(&loader, &data, &world, &settings));
+        let (expected_rom, _, _) = exit_on_error(generator.randomize(&clean_rom, seed, race_secret));
+
+        // only the bytes changed by the randomizer are compared, so other changes made by the runner are ignored
+        let mismatches = expected_rom.find_differences(&clean_rom).iter()
+            .filter(|&&address| submitted_rom.read_byte(address) != expected_rom.read_byte(address))
+            .count();
+        if mismatches == 0 {
+            println!("Seed verified");
+        } else {
+            println!("Seed does not match: {} bytes differ", mismatches);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // many seeds at once for bulk testing, each written as a spoiler and an IPS patch for the clean ROM
+    if args.len() > 2 && args[1] == "batch" {
+        if settings.race_mode {
+            println!("Race seeds cannot be generated in a batch");
+            process::exit(1);
+        }
+        let output = Path::new(settings::find_arg_value(&args, "--output").expect("Missing --output"));
+        fs::create_dir_all(output).unwrap();
+        let generator = exit_on_error(Generator::new(&loader, &data, &world, &settings));
+        let settings_string = exit_on_error(settings.to_settings_string());
+
+        let results = batch::generate_parallel(seed_range(&args), thread_count(&args), |seed| batch::catch_failure(|| {
+            let (game_rom, spoiler, _) = generator.randomize(&clean_rom, seed, None)?;
+            let mut lines = vec![
+                format!("Seed: {}", seed),
+                format!("Settings string: {}", settings_string),
+                format!("Seed hash: {}", seed::seed_hash(seed, &settings_string, None))
+            ];
+            lines.extend(spoiler);
+            fs::write(output.join(format!("{}.txt", seed)), lines.join("\n") + "\n").unwrap();
+            fs::write(output.join(format!("{}.ips", seed)), game_rom.ips_patch(&clean_rom)).unwrap();
+            Ok(())
+        }).and_then(|result| result));
+        let failures: Vec<(u64, String)> = results.into_iter()
+            .filter_map(|(seed, result)| result.err().map(|message| (seed, message)))
+            .collect();
+        for &(seed, ref message) in &failures {
+            println!("Seed {} failed: {}", seed, message);
+        }
+        println!("Generated {} seeds in {}, {} failed", seed_range(&args).count() - failures.len(), output.display(), failures.len());
+        return;
+    }
+
+    let chosen_seed: Option<u64> = settings::find_arg_value(&args, "--seed").map(|seed| seed.parse().expect("Seed must be a number"));
+    let race_secret: Option<u64> = if settings.race_mode {
+        Some(match settings::find_arg_value(&args, "--race-secret") {
+            Some(secret) => secret.parse().expect("Race secret must be a number"),
+            None => seed::random_seed()
+        })
+    } else {
+        None
     };
-    let shuffler = Shuffler::new(original_destination_exit_map, original_links);
-    let result = shuffler.shuffle_rooms(first_room, &rooms, &mut rng);
-    println!("{}", result.len());
 
-    for door in &result {
-        let &Door(destination, exit) = door;
+    // documentation errors in the world data can turn into soft-locks, so point them out before shuffling
+    for mismatch in validation::find_destination_mismatches(&world.door_table(), &clean_rom) {
+        println!("Warning: {}", mismatch);
+    }
 
-        let exit_addr1 = exit.exit_addr1;
-        let exit_addr2 = exit.exit_addr2;
-        println!("{:x} | {:x}", exit_addr1, exit_addr2);
+    let generator = exit_on_error(Generator::new(&loader, &data, &world, &settings));
+    let mut seed = chosen_seed.unwrap_or_else(seed::random_seed);
+    // a few seeds cannot be shuffled. a random seed is replaced by another one, but a seed that was asked for is an error
+    let (game_rom, spoiler, world_graph) = loop {
+        match generator.randomize(&clean_rom, seed, race_secret) {
+            Ok(result) => break result,
+            Err(message) => {
+                println!("Seed {} could not be generated: {}", seed, message);
+                if chosen_seed.is_some() {
+                    process::exit(1);
+                }
+                seed = seed::random_seed();
+            }
+        }
+    };
+    let settings_string = exit_on_error(settings.to_settings_string());
+    println!("Settings string: {}", settings_string);
+    println!("Seed hash: {}", seed::seed_hash(seed, &settings_string, race_secret));
 
-        let destination_data = destination.destination_bytes;
-        println!("{:x}, {:x}, {:x}, {:x}", destination_data[0], destination_data[1], destination_data[2], destination_data[3]);
+    match race_secret {
+        // race seeds never print a spoiler; the secret is only written to a file for the organizer to keep
+        Some(secret) => {
+            let race_info = format!("Seed: {}\nSettings string: {}\nRace secret: {}\n", seed, settings_string, secret);
+            fs::write(RACE_SECRET_PATH, race_info).unwrap();
+            println!("Race secret written to {}", RACE_SECRET_PATH);
+        }
+        None => {
+            println!("Seed: {}", seed);
+            for line in &spoiler {
+                println!("{}", line);
+            }
+        }
+    }
 
-        println!("");
-        game_rom.write_bytes(&destination_data[..], exit_addr1);
-        game_rom.write_bytes(&destination_data[..], exit_addr2);
+    // the exported graph would give away the layout of a race seed
+    let export_paths = (settings::find_arg_value(&args, "--export-dot"), settings::find_arg_value(&args, "--export-json"));
+    if race_secret.is_some() && (export_paths.0.is_some() || export_paths.1.is_some()) {
+        println!("The world graph is not exported for race seeds");
+    } else {
+        export_graph(&args, &world_graph);
     }
 
     game_rom.create_randomized_rom();
-}
\ No newline at end of file
+}
+
+// errors in the data or the options are reported without the noise of a panic
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|message| {
+        println!("{}", message);
+        process::exit(1)
+    })
+}
+
+// `<count>` seeds starting at --seed, or at 0 without it
+fn seed_range(args: &[String]) -> Range<u64> {
+    let count: u64 = args[2].parse().expect("Seed count must be a number");
+    let first_seed: u64 = settings::find_arg_value(args, "--seed").map_or(0, |seed| seed.parse().expect("Seed must be a number"));
+    first_seed..first_seed + count
+}
+
+fn thread_count(args: &[String]) -> usize {
+    settings::find_arg_value(args, "--threads").map_or_else(batch::default_thread_count, |count| count.parse().expect("Thread count must be a number"))
+}
+
+fn export_graph(args: &[String], world_graph: &WorldGraph) {
+    if let Some(path) = settings::find_arg_value(args, "--export-dot") {
+        fs::write(path, world_graph.to_dot()).unwrap();
+    }
+    if let Some(path) = settings::find_arg_value(args, "--export-json") {
+        fs::write(path, world_graph.to_json()).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use rand::{Rng, StdRng};
+use std::str::FromStr;
+
+// area themes and boss themes are shuffled separately so that bosses keep a fitting track
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TrackKind {
+    Area,
+    Boss
+}
+
+impl FromStr for TrackKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TrackKind, String> {
+        match name {
+            "area" => Ok(TrackKind::Area),
+            "boss" => Ok(TrackKind::Boss),
+            _ => Err(format!("Unknown track kind: {}", name))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MusicTrack {
+    pub id: usize,
+    pub song_addr: usize,
+    pub song_bytes: Vec<u8>,
+    pub kind: TrackKind
+}
+
+impl PartialEq for MusicTrack {
+    fn eq(&self, other: &MusicTrack) -> bool {
+        self.id == other.id
+    }
+}
+
+// the song table entry being written to and the track that now plays there
+#[derive(Clone, PartialEq, Debug)]
+pub struct TrackPlacement(pub MusicTrack, pub MusicTrack);
+
+pub struct MusicShuffler {
+    tracks: Vec<MusicTrack>
+}
+
+impl MusicShuffler {
+    pub fn new(tracks: Vec<MusicTrack>) -> MusicShuffler {
+        MusicShuffler {
+            tracks
+        }
+    }
+
+    // music is cosmetic, so this should be given an rng that is separate from the one used for the logic
+    pub fn shuffle_music(&self, rng: &mut StdRng) -> Vec<TrackPlacement> {
+        let mut placements: Vec<TrackPlacement> = Vec::new();
+        for kind in &[TrackKind::Area, TrackKind::Boss] {
+            let tracks: Vec<MusicTrack> = self.tracks.iter()
+                .filter(|&track| track.kind == *kind)
+                .cloned()
+                .collect();
+            let mut shuffled_tracks = tracks.clone();
+            rng.shuffle(&mut shuffled_tracks);
+            for (track, placed_track) in tracks.into_iter().zip(shuffled_tracks) {
+                placements.push(TrackPlacement(track, placed_track));
+            }
+        }
+        placements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn track(id: usize, kind: TrackKind) -> MusicTrack {
+        MusicTrack {
+            id,
+            song_addr: id,
+            song_bytes: vec![id as u8],
+            kind
+        }
+    }
+
+    #[test]
+    fn boss_music_stays_on_bosses() {
+        let tracks = vec![
+            track(0, TrackKind::Area),
+            track(1, TrackKind::Boss),
+            track(2, TrackKind::Area),
+            track(3, TrackKind::Boss),
+            track(4, TrackKind::Area)
+        ];
+        let music_shuffler = MusicShuffler::new(tracks.clone());
+        for seed in 0..20 {
+            let mut rng: StdRng = StdRng::from_seed(&[seed]);
+            let placements = music_shuffler.shuffle_music(&mut rng);
+
+            // every entry of the song table gets exactly one track and every track is placed exactly once
+            for track in &tracks {
+                assert_eq!(placements.iter().filter(|&TrackPlacement(entry, _)| entry == track).count(), 1);
+                assert_eq!(placements.iter().filter(|&TrackPlacement(_, placed_track)| placed_track == track).count(), 1);
+            }
+            for TrackPlacement(track, placed_track) in &placements {
+                assert_eq!(track.kind, placed_track.kind);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use rand::{Rng, StdRng};
+use std::str::FromStr;
+use super::rom::Rom;
+
+// a GBA palette holds 16 colors in RGB555 format
+pub const PALETTE_SIZE: usize = 16;
+const MAX_RGB555: u16 = 0x7FFF;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PaletteKind {
+    // the default palette of one of the four Kirbys
+    Kirby,
+    // a palette that can be unlocked with a spray paint
+    Paint
+}
+
+impl FromStr for PaletteKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<PaletteKind, String> {
+        match name {
+            "kirby" => Ok(PaletteKind::Kirby),
+            "paint" => Ok(PaletteKind::Paint),
+            _ => Err(format!("Unknown palette kind: {}", name))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PaletteEntry {
+    pub name: String,
+    pub kind: PaletteKind,
+    pub palette_addr: usize
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KirbyPalette {
+    Paint(String),
+    Random,
+    // RGB555 colors, starting from the first color of the palette
+    Custom(Vec<u16>)
+}
+
+// the palette that one of the Kirbys should use, written as `<kirby>=<paint>`, `<kirby>=random`
+// or `<kirby>=custom:<color>,<color>,...` with the colors given as hexadecimal RGB555 values
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PaletteChoice {
+    pub kirby: String,
+    pub palette: KirbyPalette
+}
+
+impl FromStr for PaletteChoice {
+    type Err = String;
+
+    fn from_str(choice: &str) -> Result<PaletteChoice, String> {
+        let mut parts = choice.splitn(2, '=');
+        let kirby = parts.next().unwrap().to_string();
+        let value = parts.next().ok_or_else(|| format!("Missing palette for {}", kirby))?;
+
+        let palette = if value == "random" {
+            KirbyPalette::Random
+        } else if let Some(colors) = value.strip_prefix("custom:") {
+            let colors: Vec<u16> = colors.split(',')
+                .map(|color| u16::from_str_radix(color, 16).map_err(|_| format!("Invalid color: {}", color)))
+                .collect::<Result<Vec<u16>, String>>()?;
+            if colors.is_empty() || colors.len() > PALETTE_SIZE {
+                return Err(format!("A palette has between 1 and {} colors", PALETTE_SIZE));
+            }
+            if let Some(color) = colors.iter().find(|&&color| color > MAX_RGB555) {
+                return Err(format!("Color is not RGB555: {:x}", color));
+            }
+            KirbyPalette::Custom(colors)
+        } else {
+            KirbyPalette::Paint(value.to_string())
+        };
+
+        Ok(PaletteChoice {
+            kirby,
+            palette
+        })
+    }
+}
+
+// the Kirby palette being written to and the colors it should contain
+#[derive(Clone, PartialEq, Debug)]
+pub struct PalettePlacement(pub PaletteEntry, pub Vec<u16>);
+
+pub struct PaletteRandomizer {
+    palettes: Vec<PaletteEntry>
+}
+
+impl PaletteRandomizer {
+    pub fn new(palettes: Vec<PaletteEntry>) -> PaletteRandomizer {
+        PaletteRandomizer {
+            palettes
+        }
+    }
+
+    // paints are copied out of the given ROM, so this works on a ROM that has already been randomized
+    pub fn resolve_palettes(&self, choices: &[PaletteChoice], rom: &Rom, rng: &mut StdRng) -> Result<Vec<PalettePlacement>, String> {
+        let paints: Vec<&PaletteEntry> = self.palettes.iter()
+            .filter(|&palette| palette.kind == PaletteKind::Paint)
+            .collect();
+
+        choices.iter()
+            .map(|choice| {
+                let kirby = self.find_palette(&choice.kirby, PaletteKind::Kirby)?;
+                let colors = match choice.palette {
+                    KirbyPalette::Paint(ref name) => rom.read_palette(self.find_palette(name, PaletteKind::Paint)?.palette_addr),
+                    KirbyPalette::Random => {
+                        let paint = rng.choose(&paints).ok_or_else(|| String::from("No spray paints are listed in palettedata.csv"))?;
+                        rom.read_palette(paint.palette_addr)
+                    },
+                    KirbyPalette::Custom(ref colors) => colors.clone()
+                };
+                Ok(PalettePlacement(kirby.clone(), colors))
+            })
+            .collect()
+    }
+
+    fn find_palette(&self, name: &str, kind: PaletteKind) -> Result<&PaletteEntry, String> {
+        self.palettes.iter()
+            .find(|&palette| palette.name == name && palette.kind == kind)
+            .ok_or_else(|| format!("Unknown {:?} palette: {} is not listed in palettedata.csv", kind, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_palette_choices() {
+        assert_eq!("pink=cherry".parse::<PaletteChoice>(), Ok(PaletteChoice { kirby: String::from("pink"), palette: KirbyPalette::Paint(String::from("cherry")) }));
+        assert_eq!("red=random".parse::<PaletteChoice>(), Ok(PaletteChoice { kirby: String::from("red"), palette: KirbyPalette::Random }));
+        assert_eq!("green=custom:7fff,1F".parse::<PaletteChoice>(), Ok(PaletteChoice { kirby: String::from("green"), palette: KirbyPalette::Custom(vec![0x7FFF, 0x1F]) }));
+        assert!("green=custom:8000".parse::<PaletteChoice>().is_err());
+        assert!("yellow".parse::<PaletteChoice>().is_err());
+    }
+
+    #[test]
+    fn undocumented_palettes_are_errors() {
+        let pink = PaletteEntry { name: String::from("pink"), kind: PaletteKind::Kirby, palette_addr: 2 };
+        let palette_randomizer = PaletteRandomizer::new(vec![pink.clone()]);
+
+        assert_eq!(palette_randomizer.find_palette("pink", PaletteKind::Kirby), Ok(&pink));
+        assert!(palette_randomizer.find_palette("red", PaletteKind::Kirby).is_err());
+        assert!(palette_randomizer.find_palette("pink", PaletteKind::Paint).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+use toml;
+use super::csv_loader::DoorTable;
+use super::shuffler::{Door, Room};
+
+// pins the exit of door `exit` to the destination of door `destination`, both given as door ids
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PlandoConnection {
+    pub exit: usize,
+    pub destination: usize
+}
+
+#[derive(Deserialize)]
+struct PlandoFile {
+    #[serde(default)]
+    connections: Vec<PlandoConnection>
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<PlandoConnection>, String> {
+    let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let file: PlandoFile = toml::from_str(&contents).map_err(|error| error.to_string())?;
+    Ok(file.connections)
+}
+
+pub fn to_doors(connections: &[PlandoConnection], door_table: &DoorTable) -> Result<Vec<Door>, String> {
+    let find_record = |id: usize| door_table.get(id)
+        .and_then(|option_record| option_record.as_ref())
+        .ok_or_else(|| format!("Unknown door in plando: {}", id));
+    connections.iter()
+        .map(|connection| Ok(Door(find_record(connection.destination)?.extract_destination(), find_record(connection.exit)?.extract_exit())))
+        .collect()
+}
+
+// excluded doors keep their vanilla destination, so they are pinned to themselves. excluding a room excludes
+// every door leading out of it and every door leading into it. doors leading in from rooms that nothing leads into,
+// such as the new-file spawn, are left out, since they are never shuffled
+pub fn excluded_connections(excluded_doors: &[usize], excluded_rooms: &[usize], rooms: &[Room]) -> Result<Vec<PlandoConnection>, String> {
+    let mut door_ids: Vec<usize> = excluded_doors.to_vec();
+    for &room_id in excluded_rooms {
+        let room = rooms.iter()
+            .find(|room| room.id == room_id)
+            .ok_or_else(|| format!("Unknown room in exclusions: {}", room_id))?;
+        door_ids.extend(room.one_way_exits.iter().chain(room.two_way_exits.iter()).map(|exit| exit.id));
+        door_ids.extend(room.one_way_entrances.iter().chain(room.two_way_entrances.iter())
+            .map(|entrance| entrance.id)
+            .filter(|&id| rooms.iter().any(|room| {
+                let has_exit = room.one_way_exits.iter().chain(room.two_way_exits.iter()).any(|exit| exit.id == id);
+                has_exit && !(room.one_way_entrances.is_empty() && room.two_way_entrances.is_empty())
+            })));
+    }
+    // a door can be excluded on its own and through its room
+    let mut seen: Vec<usize> = Vec::new();
+    door_ids.retain(|&id| if seen.contains(&id) { false } else { seen.push(id); true });
+    Ok(door_ids.into_iter()
+        .map(|id| PlandoConnection { exit: id, destination: id })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::shuffler::{Destination, Exit, ExitType};
+
+    #[test]
+    fn excluded_rooms_keep_their_doors() {
+        // room 4 is entered from room 5 through door 3, from room 6 through door 11, and from room 5 again through
+        // door 10, the way back of its two-way door 9. nothing leads into room 6
+        let rooms = vec![
+            Room { id: 4, game_room: None, one_way_entrances: vec![Destination::new(3, [0; 4]), Destination::new(11, [0; 4])],
+                two_way_entrances: vec![Destination::new(10, [0; 4])],
+                one_way_exits: vec![Exit::new(7, 0, 0, ExitType::OneWay, -1)], two_way_exits: vec![Exit::new(9, 0, 0, ExitType::TwoWay, 10)] },
+            Room { id: 5, game_room: None, one_way_entrances: vec![Destination::new(7, [0; 4])], two_way_entrances: vec![Destination::new(9, [0; 4])],
+                one_way_exits: vec![Exit::new(3, 0, 0, ExitType::OneWay, -1)], two_way_exits: vec![Exit::new(10, 0, 0, ExitType::TwoWay, 9)] },
+            Room { id: 6, game_room: None, one_way_entrances: vec![], two_way_entrances: vec![],
+                one_way_exits: vec![Exit::new(11, 0, 0, ExitType::OneWay, -1)], two_way_exits: vec![] }
+        ];
+        let connections = excluded_connections(&[2, 7], &[4], &rooms).unwrap();
+        assert_eq!(connections.iter().map(|connection| (connection.exit, connection.destination)).collect::<Vec<_>>(), vec![(2, 2), (7, 7), (9, 9), (3, 3), (10, 10)]);
+        assert!(excluded_connections(&[], &[8], &rooms).is_err());
+    }
+}
@@ -1,6 +1,55 @@
 use std::io::prelude::*;
 use std::fs::File;
+use std::path::Path;
+use super::palette::PALETTE_SIZE;
 
+// the new-file spawn is stored like any other door: the destination is written to both of these addresses
+pub const SPAWN_ADDR1: usize = 0x873450;
+pub const SPAWN_ADDR2: usize = 0x930E04;
+
+// the cartridge is mapped to 0x08000000 and mirrored twice more for the other wait states
+const CARTRIDGE_MAP_START: usize = 0x0800_0000;
+const CARTRIDGE_MAP_END: usize = 0x0E00_0000;
+const CARTRIDGE_MIRROR_SIZE: usize = 0x0200_0000;
+
+// the game title in the cartridge header, which emulators and flash carts show when picking a game
+const GAME_TITLE_ADDR: usize = 0xA0;
+const GAME_TITLE_LENGTH: usize = 12;
+// the header checksum covers the title, so it has to be recalculated or the game will not boot on hardware
+const HEADER_CHECKSUM_ADDR: usize = 0xBD;
+
+// IPS patches store 24-bit offsets and 16-bit record sizes, and end with "EOF"
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_FOOTER: &[u8] = b"EOF";
+const IPS_MAX_OFFSET: usize = 0xFF_FFFF;
+const IPS_MAX_RECORD_SIZE: usize = 0xFFFF;
+// a record at this offset would be read as the end of the patch
+const IPS_EOF_OFFSET: usize = 0x45_4F46;
+
+// turn a pointer into the cartridge, such as 0x08873450, into an offset in the ROM file
+pub fn resolve_pointer(pointer: usize) -> Option<usize> {
+    if (CARTRIDGE_MAP_START..CARTRIDGE_MAP_END).contains(&pointer) {
+        Some((pointer - CARTRIDGE_MAP_START) % CARTRIDGE_MIRROR_SIZE)
+    } else {
+        None
+    }
+}
+
+// addresses are ROM offsets, but cartridge pointers such as 08873450 are accepted as well
+pub fn parse_address(address: &str) -> Result<usize, String> {
+    let address = usize::from_str_radix(address, 16).map_err(|_| format!("Invalid address: {}", address))?;
+    Ok(resolve_pointer(address).unwrap_or(address))
+}
+
+// bytes are shown the same way as in the data files, for example "65 00 03 07"
+pub fn format_bytes(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[derive(Clone)]
 pub struct Rom {
     buffer: Vec<u8>
 }
@@ -9,11 +58,69 @@ impl Rom {
     pub fn new(mut file: File) -> Rom {
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer).unwrap();
+        Rom::from_bytes(buffer)
+    }
+
+    pub fn from_bytes(buffer: Vec<u8>) -> Rom {
         Rom {
-            buffer: buffer
+            buffer
         }
     }
 
+    pub fn read_byte(&self, address: usize) -> u8 {
+        self.buffer[address]
+    }
+
+    pub fn read_bytes(&self, address: usize, length: usize) -> &[u8] {
+        &self.buffer[address..address + length]
+    }
+
+    // values are stored little-endian
+    pub fn read_u16(&self, address: usize) -> u16 {
+        u16::from(self.buffer[address]) | (u16::from(self.buffer[address + 1]) << 8)
+    }
+
+    pub fn read_u32(&self, address: usize) -> u32 {
+        u32::from(self.read_u16(address)) | (u32::from(self.read_u16(address + 2)) << 16)
+    }
+
+    // the ROM offset that the pointer at `address` points to, if it points into the cartridge
+    pub fn read_pointer(&self, address: usize) -> Option<usize> {
+        resolve_pointer(self.read_u32(address) as usize)
+    }
+
+    // addresses where this ROM differs from `other`, which must be the same size
+    pub fn find_differences(&self, other: &Rom) -> Vec<usize> {
+        self.buffer.iter().zip(other.buffer.iter())
+            .enumerate()
+            .filter(|&(_, (byte, other_byte))| byte != other_byte)
+            .map(|(address, _)| address)
+            .collect()
+    }
+
+    // an IPS patch that turns `clean` into this ROM, so seeds can be shared without sharing the ROM
+    pub fn ips_patch(&self, clean: &Rom) -> Vec<u8> {
+        let differences = self.find_differences(clean);
+        let mut patch: Vec<u8> = IPS_HEADER.to_vec();
+        let mut index = 0;
+        while index < differences.len() {
+            let first = differences[index];
+            let start = if first == IPS_EOF_OFFSET { first - 1 } else { first };
+            let mut end = first + 1;
+            index += 1;
+            while index < differences.len() && differences[index] == end && end - start < IPS_MAX_RECORD_SIZE {
+                end += 1;
+                index += 1;
+            }
+            assert!(start <= IPS_MAX_OFFSET, "Address {:X} does not fit in an IPS patch", start);
+            patch.extend_from_slice(&[(start >> 16) as u8, (start >> 8) as u8, start as u8]);
+            patch.extend_from_slice(&[((end - start) >> 8) as u8, (end - start) as u8]);
+            patch.extend_from_slice(&self.buffer[start..end]);
+        }
+        patch.extend_from_slice(IPS_FOOTER);
+        patch
+    }
+
     pub fn write_byte(&mut self, byte: u8, address: usize) {
         self.buffer[address] = byte;
     }
@@ -24,8 +131,76 @@ impl Rom {
         }
     }
 
-    pub fn create_randomized_rom(&self) {
-        let mut rando_buffer = File::create("Randomized Kirby and the Amazing Mirror.gba").unwrap();
+    pub fn write_spawn_destination(&mut self, destination_bytes: &[u8]) {
+        self.write_bytes(destination_bytes, SPAWN_ADDR1);
+        self.write_bytes(destination_bytes, SPAWN_ADDR2);
+    }
+
+    // the title becomes "KATAM <hash>", padded with zeroes. the game never shows the header title, only emulators
+    // and flash cart menus do
+    // TODO: also draw the hash on the file select screen once the location of its text is documented
+    pub fn write_seed_hash(&mut self, hash: &str) {
+        let mut title: Vec<u8> = format!("KATAM {}", hash).into_bytes();
+        title.resize(GAME_TITLE_LENGTH, 0);
+        self.write_bytes(&title[..], GAME_TITLE_ADDR);
+        self.update_header_checksum();
+    }
+
+    fn update_header_checksum(&mut self) {
+        let checksum = self.buffer[GAME_TITLE_ADDR..HEADER_CHECKSUM_ADDR].iter()
+            .fold(0u8, |checksum, &byte| checksum.wrapping_sub(byte))
+            .wrapping_sub(0x19);
+        self.write_byte(checksum, HEADER_CHECKSUM_ADDR);
+    }
+
+    // colors are stored as little-endian RGB555 values
+    #[allow(dead_code)]
+    pub fn read_palette(&self, address: usize) -> Vec<u16> {
+        (0..PALETTE_SIZE)
+            .map(|index| self.read_u16(address + index * 2))
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn write_palette(&mut self, colors: &[u16], address: usize) {
+        for (index, color) in colors.iter().take(PALETTE_SIZE).enumerate() {
+            self.write_bytes(&[*color as u8, (*color >> 8) as u8], address + index * 2);
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        let mut rando_buffer = File::create(path).unwrap();
         rando_buffer.write_all(&self.buffer[..]).unwrap();
     }
+
+    pub fn create_randomized_rom(&self) {
+        self.save("Randomized Kirby and the Amazing Mirror.gba");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_values_and_pointers() {
+        let rom = Rom::from_bytes(vec![0x50, 0x34, 0x87, 0x08, 0x12, 0x00, 0x00, 0x03]);
+        assert_eq!(rom.read_bytes(1, 2), &[0x34, 0x87]);
+        assert_eq!(rom.read_u16(0), 0x3450);
+        assert_eq!(rom.read_u32(0), 0x0887_3450);
+        assert_eq!(rom.read_pointer(0), Some(0x873450));
+        assert_eq!(rom.read_pointer(4), None);
+        assert_eq!(resolve_pointer(0x0A87_3450), Some(0x873450));
+        assert_eq!(resolve_pointer(0x873450), None);
+    }
+
+    #[test]
+    fn ips_patch() {
+        let clean = Rom::from_bytes(vec![0; 8]);
+        let mut rom = clean.clone();
+        rom.write_bytes(&[1, 2], 1);
+        rom.write_byte(3, 6);
+        assert_eq!(rom.ips_patch(&clean), b"PATCH\x00\x00\x01\x00\x02\x01\x02\x00\x00\x06\x00\x01\x03EOF".to_vec());
+        assert_eq!(clean.ips_patch(&clean), b"PATCHEOF".to_vec());
+    }
 }
\ No newline at end of file
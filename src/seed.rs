@@ -0,0 +1,54 @@
+use rand::{self, StdRng, SeedableRng};
+
+// letters and digits that are hard to mix up when comparing hashes on stream
+const HASH_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+pub const SEED_HASH_LENGTH: usize = 6;
+
+pub fn random_seed() -> u64 {
+    rand::random::<u64>()
+}
+
+// values are split into two halves so that they produce the same rng on 32-bit and 64-bit platforms.
+// race seeds mix in a secret that is never shown to runners, so the seed and settings are not enough to regenerate them
+pub fn rng_from_seed(seed: u64, race_secret: Option<u64>) -> StdRng {
+    let mut key: Vec<usize> = vec![(seed & 0xFFFF_FFFF) as usize, (seed >> 32) as usize];
+    if let Some(secret) = race_secret {
+        key.push((secret & 0xFFFF_FFFF) as usize);
+        key.push((secret >> 32) as usize);
+    }
+    StdRng::from_seed(&key[..])
+}
+
+// a short hash that racers can compare to make sure they are playing the same seed with the same settings
+pub fn seed_hash(seed: u64, settings_string: &str, race_secret: Option<u64>) -> String {
+    let mut bytes: Vec<u8> = seed.to_le_bytes().to_vec();
+    bytes.extend_from_slice(settings_string.as_bytes());
+    if let Some(secret) = race_secret {
+        bytes.extend_from_slice(&secret.to_le_bytes());
+    }
+    let hash = fnv1a(&bytes);
+    (0..SEED_HASH_LENGTH)
+        .map(|index| HASH_ALPHABET[((hash >> (index * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+// std's hashers are not guaranteed to be stable between releases, which would break hashes shared between runners
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_hash_depends_on_seed_and_settings() {
+        let hash = seed_hash(12345, "AQk", None);
+        assert_eq!(hash.len(), SEED_HASH_LENGTH);
+        assert!(hash.bytes().all(|letter| HASH_ALPHABET.contains(&letter)));
+        assert_eq!(hash, seed_hash(12345, "AQk", None));
+        assert_ne!(hash, seed_hash(12346, "AQk", None));
+        assert_ne!(hash, seed_hash(12345, "AQU", None));
+        assert_ne!(hash, seed_hash(12345, "AQk", Some(1)));
+    }
+}
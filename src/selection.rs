@@ -0,0 +1,193 @@
+use rand::{Rng, StdRng};
+use std::str::FromStr;
+use super::shuffler::{Exit, Room};
+
+// how much more likely the favored kind of room is to be picked when dead ends are placed by preference
+const DEAD_END_BIAS: usize = 4;
+
+// decides how likely each selectable room and each open exit is to be picked while the world is built,
+// which shapes the seed without changing which connections are allowed
+pub trait SelectionStrategy {
+    fn room_weight(&self, room: &Room) -> usize;
+    // open exits are listed in the order they were opened, so the exit at index 0 is the oldest one
+    fn exit_weight(&self, exit: &Exit, index: usize, exit_count: usize) -> usize;
+}
+
+// every room and exit is equally likely, which is how seeds were always generated
+pub struct UniformSelection;
+
+impl SelectionStrategy for UniformSelection {
+    fn room_weight(&self, _room: &Room) -> usize {
+        1
+    }
+
+    fn exit_weight(&self, _exit: &Exit, _index: usize, _exit_count: usize) -> usize {
+        1
+    }
+}
+
+// rooms with more exits are favored, which keeps more exits open and makes the world branchier
+pub struct ExitCountSelection;
+
+impl SelectionStrategy for ExitCountSelection {
+    fn room_weight(&self, room: &Room) -> usize {
+        1 + room.one_way_exits.len() + room.two_way_exits.len()
+    }
+
+    fn exit_weight(&self, _exit: &Exit, _index: usize, _exit_count: usize) -> usize {
+        1
+    }
+}
+
+// older exits are favored, so rooms are added close to the start before the world grows deeper
+pub struct BreadthFirstSelection;
+
+impl SelectionStrategy for BreadthFirstSelection {
+    fn room_weight(&self, _room: &Room) -> usize {
+        1
+    }
+
+    fn exit_weight(&self, _exit: &Exit, index: usize, exit_count: usize) -> usize {
+        exit_count - index
+    }
+}
+
+// newer exits are favored, so the world grows in long paths away from the start
+pub struct DepthFirstSelection;
+
+impl SelectionStrategy for DepthFirstSelection {
+    fn room_weight(&self, _room: &Room) -> usize {
+        1
+    }
+
+    fn exit_weight(&self, _exit: &Exit, index: usize, _exit_count: usize) -> usize {
+        index + 1
+    }
+}
+
+// the strategies that can be picked in the settings; without one the selection is uniform
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    ExitCount,
+    BreadthFirst,
+    DepthFirst
+}
+
+impl FromStr for SelectionMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<SelectionMode, String> {
+        match name {
+            "exit-count" => Ok(SelectionMode::ExitCount),
+            "breadth-first" => Ok(SelectionMode::BreadthFirst),
+            "depth-first" => Ok(SelectionMode::DepthFirst),
+            _ => Err(format!("Unknown selection strategy: {}", name))
+        }
+    }
+}
+
+pub fn strategy(mode: Option<SelectionMode>) -> Box<dyn SelectionStrategy> {
+    match mode {
+        None => Box::new(UniformSelection),
+        Some(SelectionMode::ExitCount) => Box::new(ExitCountSelection),
+        Some(SelectionMode::BreadthFirst) => Box::new(BreadthFirstSelection),
+        Some(SelectionMode::DepthFirst) => Box::new(DepthFirstSelection)
+    }
+}
+
+// when dead ends are placed while the world is built, which changes how branchy a seed feels
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadEndPlacement {
+    // dead ends are favored while few rooms are placed, so they end up close to the start. they close exits early,
+    // so fewer exits are open and the world grows in longer paths
+    Early,
+    // dead ends are avoided until the other rooms run out. more exits stay open, so the world is branchier and
+    // shallower, with dead ends on the outer rooms
+    Late,
+    // dead ends are favored whenever fewer have been placed than their share of the rooms placed so far
+    Spread
+}
+
+impl DeadEndPlacement {
+    // the weights of dead ends and of other rooms, which multiply the weights of the selection strategy
+    pub fn weights(&self, placed_dead_ends: usize, dead_end_count: usize, placed_rooms: usize, room_count: usize) -> (usize, usize) {
+        match *self {
+            DeadEndPlacement::Early => (DEAD_END_BIAS, 1),
+            DeadEndPlacement::Late => (1, DEAD_END_BIAS),
+            DeadEndPlacement::Spread => {
+                let placed = placed_dead_ends * room_count;
+                let share = dead_end_count * placed_rooms;
+                if placed < share {
+                    (DEAD_END_BIAS, 1)
+                } else if placed > share {
+                    (1, DEAD_END_BIAS)
+                } else {
+                    (1, 1)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for DeadEndPlacement {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<DeadEndPlacement, String> {
+        match name {
+            "early" => Ok(DeadEndPlacement::Early),
+            "late" => Ok(DeadEndPlacement::Late),
+            "spread" => Ok(DeadEndPlacement::Spread),
+            _ => Err(format!("Unknown dead end placement: {}", name))
+        }
+    }
+}
+
+// picks an item with a probability proportional to its weight. with equal weights this draws the same
+// numbers as `rng.choose`, so seeds made with the uniform strategy stay the same
+pub fn choose_weighted<'a, T>(items: &'a [T], weights: &[usize], rng: &mut StdRng) -> Option<&'a T> {
+    let total_weight: usize = weights.iter().sum();
+    if total_weight == 0 {
+        return None;
+    }
+    let mut target = rng.gen_range(0, total_weight);
+    for (item, &weight) in items.iter().zip(weights) {
+        if target < weight {
+            return Some(item);
+        }
+        target -= weight;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use super::super::shuffler::{Destination, ExitType};
+
+    #[test]
+    fn weighted_selection() {
+        let exit = Exit::new(1, 0, 0, ExitType::TwoWay, 2);
+        let dead_end = Room { id: 1, game_room: None, one_way_entrances: vec![], two_way_entrances: vec![Destination::new(2, [0; 4])], one_way_exits: vec![], two_way_exits: vec![exit] };
+        assert!(dead_end.is_dead_end());
+        assert_eq!(ExitCountSelection.room_weight(&dead_end), 2);
+        assert_eq!(BreadthFirstSelection.exit_weight(&exit, 0, 3), 3);
+        assert_eq!(DepthFirstSelection.exit_weight(&exit, 0, 3), 1);
+        assert_eq!("depth-first".parse::<SelectionMode>(), Ok(SelectionMode::DepthFirst));
+        assert_eq!(DeadEndPlacement::Spread.weights(1, 10, 20, 100), (DEAD_END_BIAS, 1));
+        assert_eq!(DeadEndPlacement::Spread.weights(2, 10, 20, 100), (1, 1));
+
+        let mut rng: StdRng = StdRng::from_seed(&[1; 32]);
+        let mut uniform_rng = rng;
+        let items = [1, 2, 3, 4, 5];
+        for _ in 0..20 {
+            assert_eq!(choose_weighted(&items, &[1; 5], &mut rng), uniform_rng.choose(&items));
+        }
+        for _ in 0..20 {
+            assert_eq!(choose_weighted(&items, &[0, 0, 1, 0, 0], &mut rng), Some(&3));
+        }
+        assert_eq!(choose_weighted(&items, &[0; 5], &mut rng), None);
+    }
+}
@@ -0,0 +1,304 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use std::fs;
+use std::path::Path;
+use toml;
+use super::abilities::AbilityShuffleMode;
+use super::plando::{self, PlandoConnection};
+use super::selection::{DeadEndPlacement, SelectionMode};
+
+// bump this whenever the layout of the settings string changes
+const SETTINGS_STRING_VERSION: u8 = 1;
+// door ids in the settings string are stored in this many bits
+const DOOR_ID_BITS: usize = 10;
+
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub random_start: bool,
+    pub shuffle_items: bool,
+    pub ability_shuffle: Option<AbilityShuffleMode>,
+    pub guarantee_abilities: bool,
+    pub shuffle_enemies: bool,
+    // hides the spoiler and mixes a secret into the rng, see `seed::rng_from_seed`
+    pub race_mode: bool,
+    // doors placed by hand before the rest are shuffled
+    pub plando: Vec<PlandoConnection>,
+    // doors that keep their vanilla destination, by door id and by the id of the room they lead into or out of
+    pub excluded_doors: Vec<usize>,
+    pub excluded_rooms: Vec<usize>,
+    // dead ends are placed wherever they fit when this is not set
+    pub dead_end_placement: Option<DeadEndPlacement>,
+    // rooms and exits are picked uniformly when this is not set
+    pub selection_mode: Option<SelectionMode>
+    // decoupled doors, area-restricted shuffles and a boss pool are not implemented yet. they will be added at the
+    // end of the settings string in a new version. music and palette shuffles wait until the song table and the
+    // palette offsets are listed in musicdata.csv and palettedata.csv
+}
+
+impl Settings {
+    // settings are read from `--settings <file>` or `--settings-string <string>`, and flags are applied on top
+    pub fn from_args(args: &[String]) -> Result<Settings, String> {
+        let mut settings = if let Some(path) = find_arg_value(args, "--settings") {
+            Settings::load(path)?
+        } else if let Some(settings_string) = find_arg_value(args, "--settings-string") {
+            Settings::from_settings_string(settings_string)?
+        } else {
+            Settings::default()
+        };
+
+        let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+        settings.random_start |= has_flag("--random-start");
+        settings.shuffle_items |= has_flag("--shuffle-items");
+        if has_flag("--random-abilities") {
+            settings.ability_shuffle = Some(AbilityShuffleMode::Random);
+        } else if has_flag("--shuffle-abilities") {
+            settings.ability_shuffle = Some(AbilityShuffleMode::PreserveCounts);
+        }
+        settings.guarantee_abilities |= has_flag("--guarantee-abilities");
+        settings.shuffle_enemies |= has_flag("--shuffle-enemies");
+        settings.race_mode |= has_flag("--race");
+        if let Some(path) = find_arg_value(args, "--plando") {
+            settings.plando = plando::load(path)?;
+        }
+        for pair in args.windows(2).filter(|pair| pair[0] == "--exclude-door") {
+            settings.excluded_doors.push(pair[1].parse().map_err(|_| format!("Invalid door id: {}", pair[1]))?);
+        }
+        for pair in args.windows(2).filter(|pair| pair[0] == "--exclude-room") {
+            settings.excluded_rooms.push(pair[1].parse().map_err(|_| format!("Invalid room id: {}", pair[1]))?);
+        }
+        if let Some(placement) = find_arg_value(args, "--dead-ends") {
+            settings.dead_end_placement = Some(placement.parse::<DeadEndPlacement>()?);
+        }
+        if let Some(mode) = find_arg_value(args, "--selection") {
+            settings.selection_mode = Some(mode.parse::<SelectionMode>()?);
+        }
+
+        Ok(settings)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Settings, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        toml::from_str(&contents).map_err(|error| error.to_string())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        fs::write(path, toml::to_string(self).unwrap()).unwrap();
+    }
+
+    pub fn to_settings_string(&self) -> Result<String, String> {
+        let mut writer = BitWriter::new();
+        writer.write_bits(u32::from(SETTINGS_STRING_VERSION), 8);
+        writer.write_bool(self.random_start);
+        writer.write_bool(self.shuffle_items);
+        writer.write_bits(match self.ability_shuffle {
+            None => 0,
+            Some(AbilityShuffleMode::Random) => 1,
+            Some(AbilityShuffleMode::PreserveCounts) => 2
+        }, 2);
+        writer.write_bool(self.guarantee_abilities);
+        writer.write_bool(self.shuffle_enemies);
+        writer.write_bool(self.race_mode);
+        writer.write_door_id(self.plando.len())?;
+        for connection in &self.plando {
+            writer.write_door_id(connection.exit)?;
+            writer.write_door_id(connection.destination)?;
+        }
+        writer.write_id_list(&self.excluded_doors)?;
+        writer.write_id_list(&self.excluded_rooms)?;
+        writer.write_bits(match self.dead_end_placement {
+            None => 0,
+            Some(DeadEndPlacement::Early) => 1,
+            Some(DeadEndPlacement::Late) => 2,
+            Some(DeadEndPlacement::Spread) => 3
+        }, 2);
+        writer.write_bits(match self.selection_mode {
+            None => 0,
+            Some(SelectionMode::ExitCount) => 1,
+            Some(SelectionMode::BreadthFirst) => 2,
+            Some(SelectionMode::DepthFirst) => 3
+        }, 2);
+        Ok(URL_SAFE_NO_PAD.encode(writer.into_bytes()))
+    }
+
+    pub fn from_settings_string(settings_string: &str) -> Result<Settings, String> {
+        let bytes = URL_SAFE_NO_PAD.decode(settings_string.trim()).map_err(|_| String::from("Settings string is not valid base64"))?;
+        let mut reader = BitReader::new(&bytes);
+        let version = reader.read_bits(8)? as u8;
+        if version != SETTINGS_STRING_VERSION {
+            return Err(format!("Unsupported settings string version: {}", version));
+        }
+
+        let random_start = reader.read_bool()?;
+        let shuffle_items = reader.read_bool()?;
+        let ability_shuffle = match reader.read_bits(2)? {
+            0 => None,
+            1 => Some(AbilityShuffleMode::Random),
+            2 => Some(AbilityShuffleMode::PreserveCounts),
+            value => return Err(format!("Invalid ability shuffle mode: {}", value))
+        };
+        let guarantee_abilities = reader.read_bool()?;
+        let shuffle_enemies = reader.read_bool()?;
+        let race_mode = reader.read_bool()?;
+        let mut plando: Vec<PlandoConnection> = Vec::new();
+        for _ in 0..reader.read_bits(DOOR_ID_BITS)? {
+            plando.push(PlandoConnection {
+                exit: reader.read_bits(DOOR_ID_BITS)? as usize,
+                destination: reader.read_bits(DOOR_ID_BITS)? as usize
+            });
+        }
+        let excluded_doors = reader.read_id_list()?;
+        let excluded_rooms = reader.read_id_list()?;
+        let dead_end_placement = match reader.read_bits(2)? {
+            0 => None,
+            1 => Some(DeadEndPlacement::Early),
+            2 => Some(DeadEndPlacement::Late),
+            _ => Some(DeadEndPlacement::Spread)
+        };
+        let selection_mode = match reader.read_bits(2)? {
+            0 => None,
+            1 => Some(SelectionMode::ExitCount),
+            2 => Some(SelectionMode::BreadthFirst),
+            _ => Some(SelectionMode::DepthFirst)
+        };
+
+        Ok(Settings {
+            random_start,
+            shuffle_items,
+            ability_shuffle,
+            guarantee_abilities,
+            shuffle_enemies,
+            race_mode,
+            plando,
+            excluded_doors,
+            excluded_rooms,
+            dead_end_placement,
+            selection_mode
+        })
+    }
+}
+
+pub fn find_arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.windows(2)
+        .find(|pair| pair[0] == name)
+        .map(|pair| &pair[1])
+}
+
+// packs values into as few bits as possible to keep the settings string short
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_count: usize
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            bit_count: 0
+        }
+    }
+
+    fn write_bool(&mut self, value: bool) {
+        self.write_bits(value as u32, 1);
+    }
+
+    fn write_bits(&mut self, value: u32, bits: usize) {
+        for bit in 0..bits {
+            if self.bit_count.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> bit) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.bit_count % 8);
+            }
+            self.bit_count += 1;
+        }
+    }
+
+    fn write_door_id(&mut self, id: usize) -> Result<(), String> {
+        if id >= 1 << DOOR_ID_BITS {
+            return Err(format!("Id {} does not fit in the settings string", id));
+        }
+        self.write_bits(id as u32, DOOR_ID_BITS);
+        Ok(())
+    }
+
+    // room ids are stored like door ids, since there are fewer rooms than doors
+    fn write_id_list(&mut self, ids: &[usize]) -> Result<(), String> {
+        self.write_door_id(ids.len())?;
+        for &id in ids {
+            self.write_door_id(id)?;
+        }
+        Ok(())
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    bit_count: usize
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            bytes,
+            bit_count: 0
+        }
+    }
+
+    fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    fn read_bits(&mut self, bits: usize) -> Result<u32, String> {
+        let mut value = 0;
+        for bit in 0..bits {
+            let byte = self.bytes.get(self.bit_count / 8).ok_or_else(|| String::from("Settings string is too short"))?;
+            if (byte >> (self.bit_count % 8)) & 1 == 1 {
+                value |= 1 << bit;
+            }
+            self.bit_count += 1;
+        }
+        Ok(value)
+    }
+
+    fn read_id_list(&mut self) -> Result<Vec<usize>, String> {
+        let length = self.read_bits(DOOR_ID_BITS)?;
+        (0..length).map(|_| Ok(self.read_bits(DOOR_ID_BITS)? as usize)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_string_round_trip() {
+        let settings = Settings {
+            random_start: true,
+            shuffle_items: false,
+            ability_shuffle: Some(AbilityShuffleMode::PreserveCounts),
+            guarantee_abilities: true,
+            shuffle_enemies: true,
+            race_mode: true,
+            plando: vec![PlandoConnection { exit: 12, destination: 512 }],
+            excluded_doors: vec![3, 700],
+            excluded_rooms: vec![41],
+            dead_end_placement: Some(DeadEndPlacement::Spread),
+            selection_mode: Some(SelectionMode::BreadthFirst)
+        };
+        let settings_string = settings.to_settings_string().unwrap();
+        assert_eq!(Settings::from_settings_string(&settings_string), Ok(settings));
+        assert!(Settings::from_settings_string("not a settings string").is_err());
+
+        // a plando can pin more connections than fit in a byte, but ids must fit in the settings string
+        let plando = (0..300).map(|id| PlandoConnection { exit: id, destination: id }).collect::<Vec<PlandoConnection>>();
+        let settings = Settings { plando, ..Settings::default() };
+        assert_eq!(Settings::from_settings_string(&settings.to_settings_string().unwrap()), Ok(settings));
+        let settings = Settings { excluded_doors: vec![1024], ..Settings::default() };
+        assert!(settings.to_settings_string().is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use bidir_map::BidirMap;
 use rand::{Rng, StdRng};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use super::selection::{self, DeadEndPlacement, SelectionStrategy};
 
-#[derive(Copy, Clone, Eq, Hash, Debug)]
+#[derive(Copy, Clone, Eq, Debug)]
 pub struct Destination {
     pub id: usize,
     pub destination_bytes: [u8; 4]
@@ -13,6 +17,12 @@ impl PartialEq for Destination {
     }
 }
 
+impl Hash for Destination {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 impl Destination {
     pub fn new(id: usize, destination_bytes: [u8; 4]) -> Destination {
         Destination {
@@ -20,9 +30,43 @@ impl Destination {
             destination_bytes
         }
     }
+
+    pub fn data(&self) -> DestinationData {
+        DestinationData::from(self.destination_bytes)
+    }
+}
+
+// the destination bytes are the game's room number in little-endian, followed by the x and y tile the player appears on
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DestinationData {
+    pub room: u16,
+    pub x: u8,
+    pub y: u8
+}
+
+impl From<[u8; 4]> for DestinationData {
+    fn from(bytes: [u8; 4]) -> DestinationData {
+        DestinationData {
+            room: u16::from(bytes[0]) | (u16::from(bytes[1]) << 8),
+            x: bytes[2],
+            y: bytes[3]
+        }
+    }
+}
+
+impl From<DestinationData> for [u8; 4] {
+    fn from(data: DestinationData) -> [u8; 4] {
+        [data.room as u8, (data.room >> 8) as u8, data.x, data.y]
+    }
 }
 
-#[derive(Copy, Clone, Eq, Hash, Debug)]
+impl fmt::Display for DestinationData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "room {} at ({}, {})", self.room, self.x, self.y)
+    }
+}
+
+#[derive(Copy, Clone, Eq, Debug)]
 pub struct Exit {
     pub id: usize,
     pub exit_addr1: usize,
@@ -37,6 +81,12 @@ impl PartialEq for Exit {
     }
 }
 
+impl Hash for Exit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 impl Exit {
     pub fn new(
         id: usize,
@@ -64,6 +114,8 @@ pub enum ExitType {
 #[derive(Clone, Debug)]
 pub struct Room {
     pub id: usize,
+    // the room number used by the game, which several rooms in the world data can share; None if nothing leads here
+    pub game_room: Option<u16>,
     pub one_way_entrances: Vec<Destination>,
     pub two_way_entrances: Vec<Destination>,
     pub one_way_exits: Vec<Exit>,
@@ -76,38 +128,199 @@ impl PartialEq for Room {
     }
 }
 
+impl Room {
+    pub fn new(
+        id: usize,
+        one_way_entrances: Vec<Destination>,
+        two_way_entrances: Vec<Destination>,
+        one_way_exits: Vec<Exit>,
+        two_way_exits: Vec<Exit>
+    ) -> Result<Room, String> {
+        // every entrance of a room has to lead into the same room of the game
+        let mut game_rooms: Vec<u16> = one_way_entrances.iter().chain(two_way_entrances.iter())
+            .map(|destination| destination.data().room)
+            .collect();
+        game_rooms.sort();
+        game_rooms.dedup();
+        if game_rooms.len() > 1 {
+            return Err(format!("Room {} has entrances into game rooms {:?}", id, game_rooms));
+        }
+
+        Ok(Room {
+            id,
+            game_room: game_rooms.first().copied(),
+            one_way_entrances,
+            two_way_entrances,
+            one_way_exits,
+            two_way_exits
+        })
+    }
+
+    // a room entered through a single two-way door, whose only exit is the way back
+    pub fn is_dead_end(&self) -> bool {
+        self.one_way_entrances.is_empty() && self.two_way_entrances.len() == 1 && self.one_way_exits.is_empty() && self.two_way_exits.len() <= 1
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Door(pub Destination, pub Exit);
 
 pub struct Shuffler {
     original_destination_exit_map: BidirMap<Destination, Exit>,
-    original_links: BidirMap<Door, Door>
+    // the other side of each two-way door, looked up for every connection, so they are found once up front
+    corresponding_exits: HashMap<Destination, Exit>,
+    corresponding_destinations: HashMap<Exit, Destination>
 }
 
 impl Shuffler {
     pub fn new(original_destination_exit_map: BidirMap<Destination, Exit>,
                original_links: BidirMap<Door, Door>) -> Shuffler {
+        let mut corresponding_exits: HashMap<Destination, Exit> = HashMap::new();
+        let mut corresponding_destinations: HashMap<Exit, Destination> = HashMap::new();
+        // a door is searched for as the first door of a link before the second, so those entries take priority
+        for (&Door(destination, exit), &Door(linked_destination, linked_exit)) in original_links.iter() {
+            corresponding_exits.entry(destination).or_insert(linked_exit);
+            corresponding_destinations.entry(exit).or_insert(linked_destination);
+        }
+        for (&Door(destination, exit), &Door(linked_destination, linked_exit)) in original_links.iter() {
+            corresponding_exits.entry(linked_destination).or_insert(exit);
+            corresponding_destinations.entry(linked_exit).or_insert(destination);
+        }
+
         Shuffler {
             original_destination_exit_map,
-            original_links
+            corresponding_exits,
+            corresponding_destinations
         }
     }
 
-    // TODO: remove calls to .clone() by using room id
-    pub fn shuffle_rooms(&self, first_room: Room, all_rooms: &[Room], rng: &mut StdRng) -> Vec<Door> {
-        let mut unselected_rooms: Vec<Room> = all_rooms.iter().filter(|&room| *room != first_room).map(|room| room.clone()).collect();
-        let mut exits: Vec<Exit> = first_room.one_way_exits.iter().chain(first_room.two_way_exits.iter()).map(|&exit| exit).collect();
-        let mut leftover_one_way_entrances: Vec<Destination> = Vec::new();
+    // rooms that the new-file spawn can drop the player into: the spawn acts as a one-way door,
+    // so the room needs a one-way entrance and at least one exit to continue from
+    pub fn find_eligible_start_rooms<'a>(&self, all_rooms: &'a [Room]) -> Vec<&'a Room> {
+        all_rooms.iter()
+            .filter(|&room| !room.one_way_entrances.is_empty() && (!room.one_way_exits.is_empty() || !room.two_way_exits.is_empty()))
+            .collect::<Vec<&Room>>()
+    }
+
+    // the entrance that the new-file spawn leads to in the unmodified game
+    pub fn find_vanilla_spawn_entrance(&self, spawn_exit: Exit) -> Result<Destination, String> {
+        self.original_destination_exit_map.get_by_second(&spawn_exit)
+            .copied()
+            .ok_or_else(|| String::from("The world data does not say where a new file starts"))
+    }
+
+    // pinned doors are placed before shuffling: their exits and entrances are taken out of the pool, and a room
+    // that can only be reached through a pinned door is merged into the room the door leaves from.
+    // returns the rooms left to shuffle and the doors that were placed
+    pub fn place_pinned_doors(&self, pins: &[Door], all_rooms: &[Room]) -> Result<(Vec<Room>, Vec<Door>), String> {
+        let mut rooms: Vec<Room> = all_rooms.to_vec();
         let mut doors: Vec<Door> = Vec::new();
+        // whether each room, or one of the rooms merged into it, could be entered before any door was pinned
+        let mut entered: Vec<bool> = rooms.iter()
+            .map(|room| !room.one_way_entrances.is_empty() || !room.two_way_entrances.is_empty())
+            .collect();
+
+        for &Door(destination, exit) in pins {
+            // a two-way pin also places the door back, so pinning that door as well is only allowed if it matches
+            if doors.contains(&Door(destination, exit)) {
+                continue;
+            }
+            let destination_exit = *self.original_destination_exit_map.get_by_first(&destination).ok_or_else(|| format!("Unknown destination {}", destination.id))?;
+            if exit.exit_type != destination_exit.exit_type {
+                return Err(format!("Door {} and destination {} are not both one-way or both two-way", exit.id, destination.id));
+            }
 
-        let num_iterations = unselected_rooms.len();
-        for _ in 0..num_iterations {
-            let (new_exits, mut new_doors, selected_room, mut entrances) = self.connect_new_room(&exits, &unselected_rooms, rng);
+            let exit_room_index = rooms.iter()
+                .position(|room| room.one_way_exits.contains(&exit) || room.two_way_exits.contains(&exit))
+                .ok_or_else(|| format!("Door {} is not in a room or has already been placed", exit.id))?;
+            if rooms[exit_room_index].one_way_entrances.is_empty() && rooms[exit_room_index].two_way_entrances.is_empty() {
+                return Err(format!("Door {} is in a room that cannot be entered", exit.id));
+            }
+            let entrance_room_index = rooms.iter()
+                .position(|room| room.one_way_entrances.contains(&destination) || room.two_way_entrances.contains(&destination))
+                .ok_or_else(|| format!("Destination {} is not in a room or has already been placed", destination.id))?;
+
+            match exit.exit_type {
+                ExitType::OneWay => {
+                    rooms[exit_room_index].one_way_exits.retain(|&room_exit| room_exit != exit);
+                    rooms[entrance_room_index].one_way_entrances.retain(|&entrance| entrance != destination);
+                    doors.push(Door(destination, exit));
+
+                    // the pinned door is an extra way in, unless it was the only one
+                    let entrance_room = &rooms[entrance_room_index];
+                    if entrance_room_index != exit_room_index && entrance_room.one_way_entrances.is_empty() && entrance_room.two_way_entrances.is_empty() {
+                        self.merge_rooms(&mut rooms, &mut entered, exit_room_index, entrance_room_index);
+                    }
+                },
+                ExitType::TwoWay => {
+                    let reverse_exit = self.find_corresponding_exit(destination);
+                    let reverse_destination = self.find_corresponding_destination(exit);
+                    if doors.iter().any(|&Door(placed_destination, placed_exit)| placed_exit == reverse_exit || placed_destination == reverse_destination) {
+                        return Err(format!("Door {} conflicts with another pinned door", exit.id));
+                    }
+                    rooms[exit_room_index].two_way_exits.retain(|&room_exit| room_exit != exit);
+                    rooms[exit_room_index].two_way_entrances.retain(|&entrance| entrance != reverse_destination);
+                    rooms[entrance_room_index].two_way_exits.retain(|&room_exit| room_exit != reverse_exit);
+                    rooms[entrance_room_index].two_way_entrances.retain(|&entrance| entrance != destination);
+                    doors.push(Door(destination, exit));
+                    doors.push(Door(reverse_destination, reverse_exit));
+
+                    // both rooms can be reached from each other, so they are shuffled as one
+                    if entrance_room_index != exit_room_index {
+                        self.merge_rooms(&mut rooms, &mut entered, exit_room_index, entrance_room_index);
+                    }
+                }
+            }
+        }
+
+        // the shuffle only connects rooms that have an entrance left, so a room whose every way in was pinned
+        // shut, for example two dead ends pinned to each other, would be cut off from the world
+        if let Some((room, _)) = rooms.iter().zip(&entered)
+            .find(|&(room, &entered)| entered && room.one_way_entrances.is_empty() && room.two_way_entrances.is_empty()) {
+            return Err(format!("The pinned doors leave no way into room {}", room.id));
+        }
+
+        Ok((rooms, doors))
+    }
+
+    // moves the entrances and exits of one room into another, keeping the id of the room they are moved into
+    fn merge_rooms(&self, rooms: &mut Vec<Room>, entered: &mut Vec<bool>, into_index: usize, from_index: usize) {
+        entered[into_index] |= entered[from_index];
+        entered.remove(from_index);
+        let from_room = rooms[from_index].clone();
+        let into_room = &mut rooms[into_index];
+        into_room.one_way_entrances.extend(from_room.one_way_entrances);
+        into_room.two_way_entrances.extend(from_room.two_way_entrances);
+        into_room.one_way_exits.extend(from_room.one_way_exits);
+        into_room.two_way_exits.extend(from_room.two_way_exits);
+        rooms.remove(from_index);
+    }
+
+    // begin from the room the player spawns in, entering it through `spawn_entrance`. the strategy decides which
+    // room is connected next and through which open exit
+    pub fn shuffle_rooms(&self, start_room: &Room, spawn_entrance: Destination, all_rooms: &[Room], strategy: &dyn SelectionStrategy, dead_end_placement: Option<DeadEndPlacement>, rng: &mut StdRng) -> Result<Vec<Door>, String> {
+        // rooms without entrances (such as the file select) can never be connected
+        let mut unselected_rooms = UnselectedRooms::new(all_rooms, |room| {
+            room != start_room && (!room.one_way_entrances.is_empty() || !room.two_way_entrances.is_empty())
+        });
+        let mut exits: Vec<Exit> = start_room.one_way_exits.iter().chain(start_room.two_way_exits.iter()).copied().collect();
+        let mut leftover_one_way_entrances: Vec<Destination> = start_room.one_way_entrances.iter()
+            .filter(|&&entrance| entrance != spawn_entrance)
+            .copied()
+            .collect();
+        let mut doors: Vec<Door> = Vec::new();
+
+        let num_iterations = unselected_rooms.len;
+        let num_dead_ends = unselected_rooms.dead_ends;
+        for iteration in 0..num_iterations {
+            let num_placed_dead_ends = num_dead_ends - unselected_rooms.dead_ends;
+            let dead_end_weights = dead_end_placement
+                .map_or((1, 1), |placement| placement.weights(num_placed_dead_ends, num_dead_ends, iteration, num_iterations));
+            let (new_exits, mut new_doors, selected_room, mut entrances) = self.connect_new_room(&exits, &unselected_rooms, strategy, dead_end_weights, rng)?;
             exits = new_exits;
             leftover_one_way_entrances.append(&mut entrances);
             doors.append(&mut new_doors);
-            let new_unselected_rooms: Vec<Room> = unselected_rooms.iter().filter(|&room| *room != selected_room).map(|room| room.clone()).collect();
-            unselected_rooms = new_unselected_rooms;
+            unselected_rooms.remove(selected_room);
 
             // TODO: add better logging
             //println!("=======================================");
@@ -119,12 +332,12 @@ impl Shuffler {
 
         let one_way_exits = exits.iter()
             .filter(|&exit| exit.exit_type == ExitType::OneWay)
-            .map(|&exit| exit)
+            .copied()
             .collect::<Vec<Exit>>();
 
         let two_way_exits = exits.iter()
             .filter(|&exit| exit.exit_type == ExitType::TwoWay)
-            .map(|&exit| exit)
+            .copied()
             .collect::<Vec<Exit>>();
 
         assert_eq!(one_way_exits.len(), leftover_one_way_entrances.len());
@@ -144,49 +357,66 @@ impl Shuffler {
             doors.push(Door(exit1_entrance, *exit2));
         }
 
-        doors
+        Ok(doors)
     }
 
-    // return new list of exits, new doors to add, selected room to be removed from the pool, and the leftover entrances
-    fn connect_new_room(&self, exits: &[Exit], unselected_rooms: &[Room], rng: &mut StdRng) -> (Vec<Exit>, Vec<Door>, Room, Vec<Destination>) {
-        let selectable_rooms: Vec<Room> = self.find_selectable_rooms(exits, unselected_rooms);
-        let selected_room = rng.choose(&selectable_rooms).expect("Could not find room");
-        let (selected_exit, remaining_exits, new_doors, leftover_one_way_entrances) = self.make_room_connection(exits, selected_room, rng);
-        (self.calculate_new_exits(exits, selected_exit, &remaining_exits), new_doors, selected_room.clone(), leftover_one_way_entrances)
+    // `dead_end_weights` are the weights of dead ends and of other rooms, applied on top of the strategy. a weight of 0
+    // holds that kind of room back until only rooms of that kind can be placed
+    fn connect_new_room(&self, exits: &[Exit], unselected_rooms: &UnselectedRooms, strategy: &dyn SelectionStrategy, dead_end_weights: (usize, usize), rng: &mut StdRng) -> Result<RoomConnection, String> {
+        let selectable_rooms: Vec<usize> = self.find_selectable_rooms(exits, unselected_rooms);
+        let room_weights = |(dead_end_weight, other_weight): (usize, usize)| selectable_rooms.iter()
+            .map(|&index| &unselected_rooms.rooms[index])
+            .map(|room| strategy.room_weight(room) * if room.is_dead_end() { dead_end_weight } else { other_weight })
+            .collect::<Vec<usize>>();
+        let mut weights = room_weights(dead_end_weights);
+        if weights.iter().all(|&weight| weight == 0) {
+            weights = room_weights((1, 1));
+        }
+        let selected_room = *selection::choose_weighted(&selectable_rooms, &weights, rng)
+            .ok_or_else(|| String::from("Could not find a room to connect to the open exits"))?;
+        let (selected_exit, remaining_exits, new_doors, leftover_one_way_entrances) = self.make_room_connection(exits, &unselected_rooms.rooms[selected_room], strategy, rng);
+        Ok((self.calculate_new_exits(exits, selected_exit, &remaining_exits), new_doors, selected_room, leftover_one_way_entrances))
     }
 
     fn calculate_new_exits(&self, exits: &[Exit], selected_exit: Exit, remaining_exits: &[Exit]) -> Vec<Exit> {
-        exits.iter().filter(|&&exit| exit != selected_exit).chain(remaining_exits).map(|&exit| exit).collect::<Vec<Exit>>()
+        exits.iter().filter(|&&exit| exit != selected_exit).chain(remaining_exits).copied().collect::<Vec<Exit>>()
     }
 
     // return the exit selected, the remaining room exits (in case we picked a 2-way entrance), the door(s) linked, and the leftover entrances
-    fn make_room_connection(&self, exits: &[Exit], selected_room: &Room, rng: &mut StdRng) -> (Exit, Vec<Exit>, Vec<Door>, Vec<Destination>) {
+    fn make_room_connection(&self, exits: &[Exit], selected_room: &Room, strategy: &dyn SelectionStrategy, rng: &mut StdRng) -> (Exit, Vec<Exit>, Vec<Door>, Vec<Destination>) {
         let one_way_exit_exists = self.exit_type_exists(exits, ExitType::OneWay);
         let two_way_exit_exists =  self.exit_type_exists(exits, ExitType::TwoWay);
         let one_way_entrance_exists = !selected_room.one_way_entrances.is_empty();
         let two_way_entrance_exists = !selected_room.two_way_entrances.is_empty();
 
         if (one_way_exit_exists && one_way_entrance_exists) && (two_way_exit_exists && two_way_entrance_exists) {
-            let exit = rng.choose(exits).expect("Could not find exit");
+            let exit = self.choose_exit(exits, strategy, rng);
             match exit.exit_type {
                 ExitType::OneWay => self.build_room_connection_info(selected_room, rng, *exit, true),
                 ExitType::TwoWay => self.build_room_connection_info(selected_room, rng, *exit, false)
             }
         } else if one_way_exit_exists && one_way_entrance_exists {
             let one_way_exits = self.get_exits_of_type(exits, ExitType::OneWay);
-            let exit = rng.choose(&one_way_exits).expect("Could not find exit");
+            let exit = self.choose_exit(&one_way_exits, strategy, rng);
             self.build_room_connection_info(selected_room, rng, *exit, true)
         } else if two_way_exit_exists && two_way_entrance_exists {
             let two_way_exits = self.get_exits_of_type(exits, ExitType::TwoWay);
-            let exit = rng.choose(&two_way_exits).expect("Could not find exit");
+            let exit = self.choose_exit(&two_way_exits, strategy, rng);
             self.build_room_connection_info(selected_room, rng, *exit, false)
         } else {
             panic!("No valid entrance-exit pair found.");
         }
     }
 
+    fn choose_exit<'a>(&self, exits: &'a [Exit], strategy: &dyn SelectionStrategy, rng: &mut StdRng) -> &'a Exit {
+        let weights: Vec<usize> = exits.iter().enumerate()
+            .map(|(index, exit)| strategy.exit_weight(exit, index, exits.len()))
+            .collect();
+        selection::choose_weighted(exits, &weights, rng).expect("Could not find exit")
+    }
+
     fn get_exits_of_type(&self, exits: &[Exit], exit_type: ExitType) -> Vec<Exit> {
-        exits.iter().filter(|&exit| exit.exit_type == exit_type).map(|&exit| exit).collect::<Vec<Exit>>()
+        exits.iter().filter(|&exit| exit.exit_type == exit_type).copied().collect::<Vec<Exit>>()
     }
 
     fn build_room_connection_info(&self, selected_room: &Room, rng: &mut StdRng, exit: Exit, one_way: bool) -> (Exit, Vec<Exit>, Vec<Door>, Vec<Destination>) {
@@ -199,18 +429,19 @@ impl Shuffler {
         let remaining_exits = self.find_remaining_exits(selected_room, *entrance, one_way);
         let leftover_one_way_entrances: Vec<Destination> = selected_room.one_way_entrances.iter()
             .filter(|&room_entrance| room_entrance != entrance)
-            .map(|&entrance| entrance)
+            .copied()
             .collect();
         (exit, remaining_exits, doors, leftover_one_way_entrances)
     }
 
     fn find_remaining_exits(&self, selected_room: &Room, entrance: Destination, one_way: bool) -> Vec<Exit> {
         if one_way {
-            selected_room.one_way_exits.iter().chain(selected_room.two_way_exits.iter()).map(|&exit| exit).collect::<Vec<Exit>>()
+            selected_room.one_way_exits.iter().chain(selected_room.two_way_exits.iter()).copied().collect::<Vec<Exit>>()
         } else {
+            let entrance_exit = self.find_corresponding_exit(entrance);
             let two_way_exits = selected_room.two_way_exits.iter()
-                .filter(|&&exit| self.find_corresponding_exit(entrance) != exit);
-            selected_room.one_way_exits.iter().chain(two_way_exits).map(|&exit| exit).collect::<Vec<Exit>>()
+                .filter(|&&exit| exit != entrance_exit);
+            selected_room.one_way_exits.iter().chain(two_way_exits).copied().collect::<Vec<Exit>>()
         }
     }
 
@@ -223,69 +454,46 @@ impl Shuffler {
         }
     }
 
-    fn find_selectable_rooms(&self, exits: &[Exit], unselected_rooms: &[Room]) -> Vec<Room> {
-        unselected_rooms.iter()
-            .filter(|&room| self.room_does_not_block_full_access(room, exits, unselected_rooms) && self.room_has_matching_entrance(room, exits))
-            .map(|room| room.clone())
-            .collect::<Vec<Room>>()
+    // the open exits are counted once here instead of once for every room
+    fn find_selectable_rooms(&self, exits: &[Exit], unselected_rooms: &UnselectedRooms) -> Vec<usize> {
+        let open_exits = (self.count_exits_of_type(exits, ExitType::OneWay), self.count_exits_of_type(exits, ExitType::TwoWay));
+        unselected_rooms.indices()
+            .filter(|&index| {
+                let room = &unselected_rooms.rooms[index];
+                self.room_does_not_block_full_access(room, open_exits, unselected_rooms) && self.room_has_matching_entrance(room, open_exits)
+            })
+            .collect::<Vec<usize>>()
     }
 
-    fn room_has_matching_entrance(&self, room: &Room, exits: &[Exit]) -> bool {
+    fn room_has_matching_entrance(&self, room: &Room, (open_one_way_exits, open_two_way_exits): (usize, usize)) -> bool {
         let one_way_entrance_exists = !room.one_way_entrances.is_empty();
-        // search existing exits for a one-way exit and stop searching once one is found
-        let one_way_exit_exists = self.exit_type_exists(exits, ExitType::OneWay);
-
         let two_way_entrance_exists = !room.two_way_entrances.is_empty();
-        // search existing exits for a two-way exit and stop searching once one is found
-        let two_way_exit_exists = self.exit_type_exists(exits, ExitType::TwoWay);
-
-        (one_way_entrance_exists && one_way_exit_exists) || (two_way_entrance_exists && two_way_exit_exists)
+        (one_way_entrance_exists && open_one_way_exits > 0) || (two_way_entrance_exists && open_two_way_exits > 0)
     }
 
     fn exit_type_exists(&self, exits: &[Exit], exit_type: ExitType) -> bool {
-        exits.iter().skip_while(|&exit| exit.exit_type != exit_type).next().is_some()
+        exits.iter().any(|exit| exit.exit_type == exit_type)
     }
 
-    fn room_does_not_block_full_access(&self, room: &Room, exits: &[Exit], unselected_rooms: &[Room]) -> bool {
-        let (new_one_way_exits, new_two_way_exits) = self.count_new_exits(exits, room);
+    fn count_exits_of_type(&self, exits: &[Exit], exit_type: ExitType) -> usize {
+        exits.iter().filter(|&exit| exit.exit_type == exit_type).count()
+    }
 
-        // check if there is a room with a one way entrance and two way exit
-        let room_with_opposing_connections_one_to_two_exists = self.check_opposing_exit_room_exists_one_to_two(unselected_rooms);
-        // check if there is a room with a two way entrance and one way exit
-        let room_with_opposing_connections_two_to_one_exists = self.check_opposing_exit_room_exists_two_to_one(unselected_rooms);
+    // every unselected room, this one included, must still be enterable after the room is placed. a room with a
+    // one-way entrance needs a one-way exit to be left open, and any other room a two-way exit, unless a room is
+    // left that turns the one kind of exit into the other
+    fn room_does_not_block_full_access(&self, room: &Room, open_exits: (usize, usize), unselected_rooms: &UnselectedRooms) -> bool {
+        let (new_one_way_exits, new_two_way_exits) = self.count_new_exits(open_exits, room);
 
         if new_one_way_exits > 0 || new_two_way_exits > 0 {
-            unselected_rooms.iter().skip_while(|&rm| self.validate_room(
-                rm,
-                new_one_way_exits,
-                new_two_way_exits,
-                room_with_opposing_connections_one_to_two_exists,
-                room_with_opposing_connections_two_to_one_exists
-            )).next().is_none()
+            (unselected_rooms.one_way_entrances == 0 || new_one_way_exits > 0 || unselected_rooms.two_to_one_rooms > 0)
+                && (unselected_rooms.only_two_way_entrances == 0 || new_two_way_exits > 0 || unselected_rooms.one_to_two_rooms > 0)
         } else {
             false
         }
-
-    }
-
-    fn check_opposing_exit_room_exists_one_to_two(&self, unselected_rooms: &[Room]) -> bool {
-        unselected_rooms.iter().skip_while(|&room| room.one_way_entrances.is_empty() || room.two_way_exits.is_empty()).next().is_some()
-    }
-
-    fn check_opposing_exit_room_exists_two_to_one(&self, unselected_rooms: &[Room]) -> bool {
-        unselected_rooms.iter().skip_while(|&room| room.two_way_entrances.is_empty() || room.one_way_exits.is_empty()).next().is_some()
     }
 
-    fn count_new_exits(&self, exits: &[Exit], room: &Room) -> (usize, usize) {
-        let selected_one_way_exit_count: usize = exits.iter()
-            .filter(|&exit| exit.exit_type == ExitType::OneWay)
-            .collect::<Vec<&Exit>>()
-            .len();
-        let selected_two_way_exit_count: usize = exits.iter()
-            .filter(|&exit| exit.exit_type == ExitType::TwoWay)
-            .collect::<Vec<&Exit>>()
-            .len();
-
+    fn count_new_exits(&self, (selected_one_way_exit_count, selected_two_way_exit_count): (usize, usize), room: &Room) -> (usize, usize) {
         let has_one_way_entrance = !room.one_way_entrances.is_empty();
         let has_two_way_entrance = !room.two_way_entrances.is_empty();
 
@@ -313,47 +521,186 @@ impl Shuffler {
     }
 
     fn decrement_count(&self, count: usize) -> usize {
-        if count > 1 { count - 1 } else { 0 }
-    }
-
-    fn validate_room(
-        &self,
-        room_to_validate: &Room,
-        new_one_way_exits: usize,
-        new_two_way_exits: usize,
-        room_with_opposing_connections_one_to_two_exists: bool,
-        room_with_opposing_connections_two_to_one_exists: bool
-    ) -> bool {
-        let has_one_way_entrance = !room_to_validate.one_way_entrances.is_empty();
-        let has_two_way_entrance = !room_to_validate.two_way_entrances.is_empty();
-        if has_one_way_entrance {
-            (new_one_way_exits > 0) || room_with_opposing_connections_two_to_one_exists
-        } else if has_two_way_entrance {
-            (new_two_way_exits > 0) || room_with_opposing_connections_one_to_two_exists
-        } else {
-            panic!("Room has no entrances");
-        }
+        count.saturating_sub(1)
     }
 
     fn find_corresponding_exit(&self, destination: Destination) -> Exit {
-        let exit = *self.original_destination_exit_map.get_by_first(&destination).unwrap();
-        let other_door = *self.original_links.get_by_first(&Door(destination, exit))
-            .unwrap_or_else(|| self.original_links.get_by_second(&Door(destination, exit)).unwrap());
-        other_door.1
+        *self.corresponding_exits.get(&destination).unwrap_or_else(|| panic!("{:?}", destination))
     }
 
     fn find_corresponding_destination(&self, exit: Exit) -> Destination {
-        let destination = *self.original_destination_exit_map.get_by_second(&exit).unwrap();
-        let other_door = *self.original_links.get_by_first(&Door(destination, exit))
-            .unwrap_or_else(|| self.original_links.get_by_second(&Door(destination, exit)).unwrap_or_else(|| panic!("{:?}", exit)));
-        other_door.0
+        *self.corresponding_destinations.get(&exit).unwrap_or_else(|| panic!("{:?}", exit))
+    }
+}
+
+// new list of exits, new doors to add, index of the selected room to be removed from the pool, and the leftover entrances
+type RoomConnection = (Vec<Exit>, Vec<Door>, usize, Vec<Destination>);
+
+// the rooms still to be connected, kept as indices into the rooms being shuffled. the kinds of rooms that decide
+// whether placing a room blocks the others are counted as rooms are removed, instead of checking every room again
+struct UnselectedRooms<'a> {
+    rooms: &'a [Room],
+    unselected: Vec<bool>,
+    len: usize,
+    dead_ends: usize,
+    one_way_entrances: usize,
+    // rooms that can only be entered through a two-way door
+    only_two_way_entrances: usize,
+    // rooms with a one-way entrance and a two-way exit
+    one_to_two_rooms: usize,
+    // rooms with a two-way entrance and a one-way exit
+    two_to_one_rooms: usize
+}
+
+impl<'a> UnselectedRooms<'a> {
+    fn new<F: Fn(&Room) -> bool>(rooms: &'a [Room], include: F) -> UnselectedRooms<'a> {
+        let mut unselected_rooms = UnselectedRooms {
+            rooms,
+            unselected: vec![false; rooms.len()],
+            len: 0,
+            dead_ends: 0,
+            one_way_entrances: 0,
+            only_two_way_entrances: 0,
+            one_to_two_rooms: 0,
+            two_to_one_rooms: 0
+        };
+        for (index, room) in rooms.iter().enumerate() {
+            if include(room) {
+                unselected_rooms.unselected[index] = true;
+                unselected_rooms.update_counts(index, true);
+            }
+        }
+        unselected_rooms
+    }
+
+    // in the order of the rooms being shuffled, so the rng picks the same rooms as when they were kept in a list
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.rooms.len()).filter(move |&index| self.unselected[index])
+    }
+
+    fn remove(&mut self, index: usize) {
+        assert!(self.unselected[index], "Room {} has already been selected", self.rooms[index].id);
+        self.unselected[index] = false;
+        self.update_counts(index, false);
+    }
+
+    fn update_counts(&mut self, index: usize, added: bool) {
+        let room = &self.rooms[index];
+        let has_one_way_entrance = !room.one_way_entrances.is_empty();
+        let has_two_way_entrance = !room.two_way_entrances.is_empty();
+        let mut counts = [
+            (&mut self.len, true),
+            (&mut self.dead_ends, room.is_dead_end()),
+            (&mut self.one_way_entrances, has_one_way_entrance),
+            (&mut self.only_two_way_entrances, !has_one_way_entrance && has_two_way_entrance),
+            (&mut self.one_to_two_rooms, has_one_way_entrance && !room.two_way_exits.is_empty()),
+            (&mut self.two_to_one_rooms, has_two_way_entrance && !room.one_way_exits.is_empty())
+        ];
+        for (count, counted) in counts.iter_mut() {
+            if *counted && added {
+                **count += 1;
+            } else if *counted {
+                **count -= 1;
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn destination_data_round_trip() {
+        let data = DestinationData::from([0x3C, 0x03, 0x67, 0x06]);
+        assert_eq!(data, DestinationData { room: 828, x: 0x67, y: 0x06 });
+        assert_eq!(<[u8; 4]>::from(data), [0x3C, 0x03, 0x67, 0x06]);
+        assert_eq!(data.to_string(), "room 828 at (103, 6)");
+    }
+
+    fn door(id: usize, exit_type: ExitType, linked_door_id: i32) -> Door {
+        Door(Destination::new(id, [id as u8, 0, 0, 0]), Exit::new(id, 0, 0, exit_type, linked_door_id))
+    }
+
+    fn room(id: usize, entrances: &[Door], exits: &[Door]) -> Room {
+        let (one_way_entrances, two_way_entrances): (Vec<Door>, Vec<Door>) = entrances.iter().partition(|door| door.1.exit_type == ExitType::OneWay);
+        let (one_way_exits, two_way_exits): (Vec<Door>, Vec<Door>) = exits.iter().partition(|door| door.1.exit_type == ExitType::OneWay);
+        Room {
+            id,
+            game_room: None,
+            one_way_entrances: one_way_entrances.iter().map(|door| door.0).collect(),
+            two_way_entrances: two_way_entrances.iter().map(|door| door.0).collect(),
+            one_way_exits: one_way_exits.iter().map(|door| door.1).collect(),
+            two_way_exits: two_way_exits.iter().map(|door| door.1).collect()
+        }
+    }
+
+    #[test]
+    fn pinned_doors_are_placed_before_shuffling() {
+        // rooms 1 and 2 are linked by doors 1 and 2, rooms 2 and 3 by doors 3 and 4, and door 5 leads one way from 2 to 3
+        let doors = [
+            door(1, ExitType::TwoWay, 2), door(2, ExitType::TwoWay, 1),
+            door(3, ExitType::TwoWay, 4), door(4, ExitType::TwoWay, 3),
+            door(5, ExitType::OneWay, -1)
+        ];
+        let rooms = vec![
+            room(1, &[doors[1]], &[doors[0]]),
+            room(2, &[doors[0], doors[3]], &[doors[1], doors[2], doors[4]]),
+            room(3, &[doors[2], doors[4]], &[doors[3]])
+        ];
+        let mut destination_exit_map = BidirMap::new();
+        for &Door(destination, exit) in &doors {
+            destination_exit_map.insert(destination, exit);
+        }
+        let mut links = BidirMap::new();
+        links.insert(doors[0], doors[1]);
+        links.insert(doors[2], doors[3]);
+        let shuffler = Shuffler::new(destination_exit_map, links);
+
+        // door 1 now leads into room 3, so door 4 leads back into room 1 and both rooms are shuffled as one.
+        // pinning the way back as well is allowed. door 5 then becomes the only way into them, so they join room 2
+        let pins = [Door(doors[2].0, doors[0].1), Door(doors[1].0, doors[3].1), Door(doors[4].0, doors[4].1)];
+        let (shuffled_rooms, placed_doors) = shuffler.place_pinned_doors(&pins, &rooms).unwrap();
+        assert_eq!(placed_doors, pins.to_vec());
+        assert_eq!(shuffled_rooms.len(), 1);
+        assert_eq!(shuffled_rooms[0].id, 2);
+        assert_eq!(shuffled_rooms[0].two_way_entrances, vec![doors[0].0, doors[3].0]);
+        assert_eq!(shuffled_rooms[0].two_way_exits, vec![doors[1].1, doors[2].1]);
+        assert!(shuffled_rooms[0].one_way_entrances.is_empty() && shuffled_rooms[0].one_way_exits.is_empty());
+
+        // a door cannot be pinned twice, and a one-way exit cannot lead to a two-way entrance
+        assert!(shuffler.place_pinned_doors(&[pins[0], Door(doors[3].0, doors[0].1)], &rooms).is_err());
+        assert!(shuffler.place_pinned_doors(&[Door(doors[0].0, doors[4].1)], &rooms).is_err());
+
+        // rooms 1 and 3 are dead ends off room 2, pinning them to each other would cut both off from the world
+        let rooms = vec![
+            room(1, &[doors[1]], &[doors[0]]),
+            room(2, &[doors[0], doors[3]], &[doors[1], doors[2]]),
+            room(3, &[doors[2]], &[doors[3]])
+        ];
+        assert!(shuffler.place_pinned_doors(&[Door(doors[2].0, doors[0].1)], &rooms).is_err());
+    }
+
+    #[test]
+    fn unselected_rooms_are_counted_by_kind() {
+        let rooms = vec![
+            room(1, &[door(1, ExitType::TwoWay, -1)], &[door(2, ExitType::OneWay, -1)]),
+            room(2, &[door(3, ExitType::OneWay, -1)], &[door(4, ExitType::TwoWay, -1)]),
+            room(3, &[], &[door(5, ExitType::TwoWay, -1)]),
+            room(4, &[door(6, ExitType::TwoWay, -1)], &[door(7, ExitType::TwoWay, -1)])
+        ];
+        let mut unselected_rooms = UnselectedRooms::new(&rooms, |room| !room.one_way_entrances.is_empty() || !room.two_way_entrances.is_empty());
+        assert_eq!(unselected_rooms.indices().collect::<Vec<usize>>(), vec![0, 1, 3]);
+        assert_eq!((unselected_rooms.len, unselected_rooms.dead_ends, unselected_rooms.one_way_entrances), (3, 1, 1));
+        assert_eq!((unselected_rooms.only_two_way_entrances, unselected_rooms.one_to_two_rooms, unselected_rooms.two_to_one_rooms), (2, 1, 1));
+
+        unselected_rooms.remove(0);
+        assert_eq!(unselected_rooms.indices().collect::<Vec<usize>>(), vec![1, 3]);
+        assert_eq!((unselected_rooms.len, unselected_rooms.only_two_way_entrances, unselected_rooms.two_to_one_rooms), (2, 1, 0));
+    }
 }
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use super::graph::WorldGraph;
+
+// the shape of one generated seed
+pub struct SeedStats {
+    // doors between the start room and each boss room, None if the boss cannot be reached
+    pub boss_distances: Vec<Option<usize>>,
+    pub dead_ends: usize,
+    // the furthest any reachable room is from the closest hub, or from the start room when no hub is marked
+    pub max_hub_distance: usize,
+    // the doors on the shortest paths from the start room to every room, once for each path they are on
+    pub door_usage: HashMap<usize, usize>
+}
+
+impl SeedStats {
+    pub fn new(graph: &WorldGraph, start_room: usize, boss_rooms: &[usize], hub_rooms: &[usize]) -> SeedStats {
+        let paths = graph.shortest_paths(start_room);
+        let boss_distances = boss_rooms.iter()
+            .map(|room| paths.get(room).map(|path| path.len()))
+            .collect();
+
+        let hub_paths: Vec<HashMap<usize, Vec<usize>>> = if hub_rooms.is_empty() {
+            vec![paths.clone()]
+        } else {
+            hub_rooms.iter().map(|&hub| graph.shortest_paths(hub)).collect()
+        };
+        let max_hub_distance = graph.rooms.iter()
+            .filter_map(|node| hub_paths.iter().filter_map(|hub| hub.get(&node.room).map(|path| path.len())).min())
+            .max()
+            .unwrap_or(0);
+
+        let mut door_usage: HashMap<usize, usize> = HashMap::new();
+        for door in paths.values().flatten() {
+            *door_usage.entry(*door).or_insert(0) += 1;
+        }
+
+        SeedStats {
+            boss_distances,
+            dead_ends: graph.dead_ends().len(),
+            max_hub_distance,
+            door_usage
+        }
+    }
+}
+
+// collects the stats of many seeds, including the ones that failed to generate
+pub struct Statistics {
+    boss_rooms: Vec<usize>,
+    hub_rooms: Vec<usize>,
+    seeds: Vec<SeedStats>,
+    // how often each failure happened, by its message
+    failures: HashMap<String, usize>
+}
+
+impl Statistics {
+    pub fn new(boss_rooms: Vec<usize>, hub_rooms: Vec<usize>) -> Statistics {
+        Statistics {
+            boss_rooms,
+            hub_rooms,
+            seeds: Vec::new(),
+            failures: HashMap::new()
+        }
+    }
+
+    pub fn add(&mut self, seed: SeedStats) {
+        self.seeds.push(seed);
+    }
+
+    pub fn add_failure(&mut self, message: String) {
+        *self.failures.entry(message).or_insert(0) += 1;
+    }
+
+    pub fn report(&self) -> Vec<String> {
+        let failure_count: usize = self.failures.values().sum();
+        let attempts = self.seeds.len() + failure_count;
+        // a random seed that fails is replaced by another one, so the failure rate is how often a seed is rerolled
+        let mut lines = vec![
+            format!("Seeds: {} generated, {} failed ({:.1}%)", self.seeds.len(), failure_count, percentage(failure_count, attempts)),
+            format!("Rerolls: {:.1}% of random seeds are rerolled, {:.3} rerolls per generated seed on average",
+                percentage(failure_count, attempts), failure_count as f64 / self.seeds.len().max(1) as f64)
+        ];
+        let mut failures: Vec<(&String, &usize)> = self.failures.iter().collect();
+        failures.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (message, count) in failures {
+            lines.push(format!("  {} x {}", count, message));
+        }
+        if self.seeds.is_empty() {
+            return lines;
+        }
+
+        if self.boss_rooms.is_empty() {
+            lines.push(String::from("Boss distances: no boss rooms are marked in the world data"));
+        }
+        for (index, room) in self.boss_rooms.iter().enumerate() {
+            let distances: Vec<usize> = self.seeds.iter().filter_map(|seed| seed.boss_distances[index]).collect();
+            let unreachable = self.seeds.len() - distances.len();
+            lines.push(format!("Distance to boss room {}: {}, unreachable in {} seeds", room, summarize(&distances), unreachable));
+        }
+        lines.push(format!("Dead ends: {}", summarize(&self.seeds.iter().map(|seed| seed.dead_ends).collect::<Vec<usize>>())));
+        let hub = if self.hub_rooms.is_empty() { "the start room (no hub is marked in the world data)" } else { "the hub" };
+        lines.push(format!("Max distance from {}: {}", hub, summarize(&self.seeds.iter().map(|seed| seed.max_hub_distance).collect::<Vec<usize>>())));
+
+        // doors that many paths go through are the ones a seed funnels the player through
+        let mut door_usage: HashMap<usize, usize> = HashMap::new();
+        for seed in &self.seeds {
+            for (&door, &count) in &seed.door_usage {
+                *door_usage.entry(door).or_insert(0) += count;
+            }
+        }
+        let mut door_usage: Vec<(usize, usize)> = door_usage.into_iter().collect();
+        door_usage.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        lines.push(String::from("Door usage (shortest paths from the start room through each door, per seed):"));
+        for (door, count) in door_usage {
+            lines.push(format!("  door {}: {:.2}", door, count as f64 / self.seeds.len() as f64));
+        }
+        lines
+    }
+}
+
+fn percentage(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+// the distribution of a value over all seeds
+fn summarize(values: &[usize]) -> String {
+    if values.is_empty() {
+        return String::from("no values");
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort();
+    let mean = sorted.iter().sum::<usize>() as f64 / sorted.len() as f64;
+    format!("min {}, median {}, mean {:.2}, max {}", sorted[0], sorted[sorted.len() / 2], mean, sorted[sorted.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::graph::{GraphEdge, GraphNode};
+
+    fn node(room: usize, exits: &[(usize, usize)]) -> GraphNode {
+        GraphNode {
+            room,
+            game_room: None,
+            exits: exits.iter()
+                .map(|&(door, to_room)| GraphEdge { door, destination: door, to_room, one_way: false })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn seed_stats() {
+        // 1 - 2 - 3 with 4 off 2, so every room but 2 is a dead end
+        let graph = WorldGraph {
+            rooms: vec![node(1, &[(1, 2)]), node(2, &[(2, 1), (3, 3), (5, 4)]), node(3, &[(4, 2)]), node(4, &[(6, 2)])],
+            unplaced_doors: vec![]
+        };
+        let seed = SeedStats::new(&graph, 1, &[3, 5], &[2]);
+        assert_eq!(seed.boss_distances, vec![Some(2), None]);
+        assert_eq!(seed.dead_ends, 3);
+        assert_eq!(seed.max_hub_distance, 1);
+        assert_eq!(seed.door_usage[&1], 3);
+
+        let mut statistics = Statistics::new(vec![3, 5], vec![2]);
+        statistics.add(seed);
+        statistics.add_failure(String::from("Could not find room"));
+        let report = statistics.report();
+        assert_eq!(report[0], "Seeds: 1 generated, 1 failed (50.0%)");
+        assert_eq!(report[1], "Rerolls: 50.0% of random seeds are rerolled, 1.000 rerolls per generated seed on average");
+        assert!(report.contains(&String::from("Distance to boss room 5: no values, unreachable in 1 seeds")));
+        assert!(report.contains(&String::from("  door 1: 3.00")));
+    }
+}
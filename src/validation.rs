@@ -0,0 +1,94 @@
+use std::fmt;
+use super::csv_loader::DoorTable;
+use super::rom::Rom;
+use super::shuffler::DestinationData;
+
+// a door whose exit addresses in the clean ROM do not both hold the destination documented in the world data
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DestinationMismatch {
+    pub door_id: usize,
+    pub documented_bytes: [u8; 4],
+    pub exit_bytes1: Vec<u8>,
+    pub exit_bytes2: Vec<u8>
+}
+
+impl DestinationMismatch {
+    // when both exits hold the same destination, only the data file is wrong
+    pub fn exits_agree(&self) -> bool {
+        self.exit_bytes1 == self.exit_bytes2
+    }
+}
+
+impl fmt::Display for DestinationMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Door {}: world data has {}, exitaddr1 has {}, exitaddr2 has {}", self.door_id,
+               DestinationData::from(self.documented_bytes), decode(&self.exit_bytes1), decode(&self.exit_bytes2))
+    }
+}
+
+fn decode(bytes: &[u8]) -> DestinationData {
+    DestinationData::from([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// the randomizer writes the same destination to both exit addresses, so any difference here points to a documentation error
+pub fn find_destination_mismatches(door_table: &DoorTable, clean_rom: &Rom) -> Vec<DestinationMismatch> {
+    door_table.iter()
+        .filter_map(|option_record| option_record.as_ref())
+        .filter_map(|record| {
+            let documented_bytes = record.extract_destination().destination_bytes;
+            let exit = record.extract_exit();
+            let exit_bytes1 = clean_rom.read_bytes(exit.exit_addr1, 4);
+            let exit_bytes2 = clean_rom.read_bytes(exit.exit_addr2, 4);
+            if exit_bytes1 == documented_bytes && exit_bytes2 == documented_bytes {
+                None
+            } else {
+                Some(DestinationMismatch {
+                    door_id: record.doorid,
+                    documented_bytes,
+                    exit_bytes1: exit_bytes1.to_vec(),
+                    exit_bytes2: exit_bytes2.to_vec()
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::csv_loader::DoorRecord;
+
+    fn record(doorid: usize, destination: &str, exitaddr1: &str, exitaddr2: &str) -> Option<DoorRecord> {
+        Some(DoorRecord {
+            doorid,
+            destination: String::from(destination),
+            exitaddr1: String::from(exitaddr1),
+            exitaddr2: String::from(exitaddr2),
+            isoneway: true,
+            linkeddoor: None
+        })
+    }
+
+    #[test]
+    fn mismatched_exits_are_flagged() {
+        let clean_rom = Rom::from_bytes(vec![
+            0x65, 0x00, 0x03, 0x07,
+            0x65, 0x00, 0x03, 0x07,
+            0x6A, 0x00, 0x02, 0x09,
+            0x6A, 0x00, 0x02, 0x08
+        ]);
+        let door_table = vec![
+            record(0, "65 00 03 07", "0", "4"),
+            record(1, "6A 00 02 09", "8", "C"),
+            record(2, "65 00 03 08", "0", "4")
+        ];
+        let mismatches = find_destination_mismatches(&door_table, &clean_rom);
+
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].door_id, 1);
+        assert!(!mismatches[0].exits_agree());
+        assert_eq!(mismatches[1].door_id, 2);
+        assert!(mismatches[1].exits_agree());
+        assert_eq!(mismatches[1].to_string(), "Door 2: world data has room 101 at (3, 8), exitaddr1 has room 101 at (3, 7), exitaddr2 has room 101 at (3, 7)");
+    }
+}
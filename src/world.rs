@@ -0,0 +1,392 @@
+use std::fs;
+use std::path::Path;
+use toml;
+use super::csv_loader::{DoorRecord, DoorTable};
+use super::rom;
+use super::shuffler::{Destination, DestinationData, Exit, ExitType, Room};
+
+// bump this and add a migration whenever the layout of the area files changes
+pub const WORLD_VERSION: u32 = 1;
+
+type Migration = fn(&mut toml::Table) -> Result<(), String>;
+
+// `MIGRATIONS[n]` turns an area file of version n + 1 into version n + 2
+const MIGRATIONS: [Migration; 0] = [];
+
+// the game numbers its rooms by area, with the area in the hundreds digit of the room number.
+// rooms that nothing leads into, like the new-file spawn, are kept in area 0
+pub fn area_of(game_room: Option<u16>) -> u16 {
+    game_room.map_or(0, |game_room| game_room / 100)
+}
+
+// a door is listed under the room that its exit is in, and puts the player on tile (x, y) of the game room it leads to
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct DoorDefinition {
+    pub id: usize,
+    pub destination_room: u16,
+    pub destination_x: u8,
+    pub destination_y: u8,
+    pub exit_addr1: String,
+    pub exit_addr2: String,
+    pub one_way: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linked_door: Option<usize>
+}
+
+impl DoorDefinition {
+    fn from_record(record: &DoorRecord) -> DoorDefinition {
+        let destination = record.extract_destination().data();
+        DoorDefinition {
+            id: record.doorid,
+            destination_room: destination.room,
+            destination_x: destination.x,
+            destination_y: destination.y,
+            exit_addr1: record.exitaddr1.clone(),
+            exit_addr2: record.exitaddr2.clone(),
+            one_way: record.isoneway,
+            linked_door: record.linkeddoor.map(|linked_door| linked_door as usize)
+        }
+    }
+
+    pub fn destination(&self) -> DestinationData {
+        DestinationData {
+            room: self.destination_room,
+            x: self.destination_x,
+            y: self.destination_y
+        }
+    }
+
+    pub fn set_destination(&mut self, destination: DestinationData) {
+        self.destination_room = destination.room;
+        self.destination_x = destination.x;
+        self.destination_y = destination.y;
+    }
+
+    fn to_record(&self) -> DoorRecord {
+        DoorRecord {
+            doorid: self.id,
+            destination: rom::format_bytes(&<[u8; 4]>::from(self.destination())),
+            exitaddr1: self.exit_addr1.clone(),
+            exitaddr2: self.exit_addr2.clone(),
+            isoneway: self.one_way,
+            linkeddoor: self.linked_door.map(|linked_door| linked_door as i32)
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RoomDefinition {
+    pub id: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // ids of the doors that lead into this room
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entrances: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub doors: Vec<DoorDefinition>,
+    // rooms that `katam-rando stats` measures distances to and from
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub boss: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub hub: bool,
+    // abilities or items needed to get through the room, such as "hammer"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<String>,
+    // which doors out of the room can be reached from which entrances. without any, every entrance reaches every door
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub internal_edges: Vec<InternalEdge>
+}
+
+// a path inside a room, from the door with id `from` that leads into the room to the door `to` that leaves it
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct InternalEdge {
+    pub from: usize,
+    pub to: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<String>
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// one file of the world definition
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AreaDefinition {
+    pub version: u32,
+    pub area: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub rooms: Vec<RoomDefinition>,
+    // documented doors whose exit has not been placed in a room yet
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unplaced_doors: Vec<DoorDefinition>
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct World {
+    pub areas: Vec<AreaDefinition>
+}
+
+impl World {
+    // every `.toml` file in the directory is one area
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<World, String> {
+        let mut paths: Vec<_> = fs::read_dir(directory).map_err(|error| error.to_string())?
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+            .collect();
+        paths.sort();
+
+        let mut files: Vec<(String, String)> = Vec::new();
+        for path in paths {
+            let contents = fs::read_to_string(&path).map_err(|error| error.to_string())?;
+            files.push((path.display().to_string(), contents));
+        }
+        World::from_area_files(&files)
+    }
+
+    // each file is given as its name, which is only used in error messages, and its contents
+    pub fn from_area_files<N: AsRef<str>, C: AsRef<str>>(files: &[(N, C)]) -> Result<World, String> {
+        let areas = files.iter()
+            .map(|(name, contents)| parse_area(contents.as_ref()).map_err(|error| format!("{}: {}", name.as_ref(), error)))
+            .collect::<Result<Vec<AreaDefinition>, String>>()?;
+        Ok(World {
+            areas
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, directory: P) {
+        fs::create_dir_all(&directory).unwrap();
+        for area in &self.areas {
+            let path = directory.as_ref().join(format!("area{}.toml", area.area));
+            fs::write(path, toml::to_string(area).unwrap()).unwrap();
+        }
+    }
+
+    // converts the two data files read by `CsvLoader` into areas
+    pub fn from_csv(door_table: &DoorTable, rooms: &[Room]) -> World {
+        let mut areas: Vec<AreaDefinition> = Vec::new();
+        let mut placed_doors: Vec<usize> = Vec::new();
+
+        let mut sorted_rooms: Vec<&Room> = rooms.iter().collect();
+        sorted_rooms.sort_by_key(|room| room.id);
+        for room in sorted_rooms {
+            let mut door_ids: Vec<usize> = room.one_way_exits.iter().chain(room.two_way_exits.iter())
+                .map(|exit| exit.id)
+                .collect();
+            door_ids.sort();
+            placed_doors.extend(door_ids.iter().cloned());
+
+            let room_definition = RoomDefinition {
+                id: room.id,
+                name: None,
+                entrances: room.one_way_entrances.iter().chain(room.two_way_entrances.iter()).map(|destination| destination.id).collect(),
+                doors: door_ids.iter()
+                    .map(|&id| DoorDefinition::from_record(door_table[id].as_ref().unwrap()))
+                    .collect(),
+                boss: false,
+                hub: false,
+                requirements: Vec::new(),
+                internal_edges: Vec::new()
+            };
+            find_area(&mut areas, area_of(room.game_room)).rooms.push(room_definition);
+        }
+
+        for record in door_table.iter().filter_map(|option_record| option_record.as_ref()) {
+            if !placed_doors.contains(&record.doorid) {
+                let door = DoorDefinition::from_record(record);
+                find_area(&mut areas, area_of(Some(door.destination_room))).unplaced_doors.push(door);
+            }
+        }
+
+        areas.sort_by_key(|area| area.area);
+        World {
+            areas
+        }
+    }
+
+    pub fn door_table(&self) -> DoorTable {
+        let doors: Vec<&DoorDefinition> = self.areas.iter()
+            .flat_map(|area| area.rooms.iter().flat_map(|room| room.doors.iter()).chain(area.unplaced_doors.iter()))
+            .collect();
+        let max_id = doors.iter().map(|door| door.id).max().expect("The world has no doors");
+        let mut door_table: DoorTable = vec![None; max_id + 1];
+        for door in doors {
+            door_table[door.id] = Some(door.to_record());
+        }
+        door_table
+    }
+
+    // mistakes in the world data, such as a room entered from several rooms of the game, are returned as errors
+    pub fn rooms(&self, door_table: &DoorTable) -> Result<Vec<Room>, String> {
+        let mut rooms: Vec<Room> = self.areas.iter()
+            .flat_map(|area| area.rooms.iter())
+            .map(|room| {
+                let find_record = |id: usize| door_table.get(id)
+                    .and_then(|record| record.as_ref())
+                    .ok_or_else(|| format!("Unknown door {} in room {}", id, room.id));
+                // the shuffler does not use internal edges yet, but they have to describe doors of the room
+                for edge in &room.internal_edges {
+                    if !room.entrances.contains(&edge.from) || !room.doors.iter().any(|door| door.id == edge.to) {
+                        return Err(format!("Internal edge from door {} to door {} is not inside room {}", edge.from, edge.to, room.id));
+                    }
+                }
+                // whether an entrance is one-way is taken from the door leading into it
+                let mut one_way_entrances: Vec<Destination> = Vec::new();
+                let mut two_way_entrances: Vec<Destination> = Vec::new();
+                for &id in &room.entrances {
+                    let record = find_record(id)?;
+                    if record.isoneway {
+                        one_way_entrances.push(record.extract_destination());
+                    } else {
+                        two_way_entrances.push(record.extract_destination());
+                    }
+                }
+                let exits: Vec<Exit> = room.doors.iter()
+                    .map(|door| find_record(door.id).map(|record| record.extract_exit()))
+                    .collect::<Result<Vec<Exit>, String>>()?;
+                Room::new(
+                    room.id,
+                    one_way_entrances,
+                    two_way_entrances,
+                    exits.iter().filter(|exit| exit.exit_type == ExitType::OneWay).copied().collect(),
+                    exits.iter().filter(|exit| exit.exit_type == ExitType::TwoWay).copied().collect()
+                )
+            })
+            .collect::<Result<Vec<Room>, String>>()?;
+        rooms.sort_by_key(|room| room.id);
+        Ok(rooms)
+    }
+
+    pub fn boss_rooms(&self) -> Vec<usize> {
+        self.room_ids(|room| room.boss)
+    }
+
+    pub fn hub_rooms(&self) -> Vec<usize> {
+        self.room_ids(|room| room.hub)
+    }
+
+    fn room_ids<F: Fn(&RoomDefinition) -> bool>(&self, predicate: F) -> Vec<usize> {
+        self.areas.iter()
+            .flat_map(|area| area.rooms.iter())
+            .filter(|&room| predicate(room))
+            .map(|room| room.id)
+            .collect()
+    }
+
+    pub fn set_destination_bytes(&mut self, door_id: usize, destination_bytes: [u8; 4]) {
+        let door = self.areas.iter_mut()
+            .flat_map(|area| area.rooms.iter_mut().flat_map(|room| room.doors.iter_mut()).chain(area.unplaced_doors.iter_mut()))
+            .find(|door| door.id == door_id)
+            .unwrap_or_else(|| panic!("Unknown door {}", door_id));
+        door.set_destination(DestinationData::from(destination_bytes));
+    }
+}
+
+fn find_area(areas: &mut Vec<AreaDefinition>, area: u16) -> &mut AreaDefinition {
+    if let Some(index) = areas.iter().position(|definition| definition.area == area) {
+        return &mut areas[index];
+    }
+    areas.push(AreaDefinition {
+        version: WORLD_VERSION,
+        area,
+        name: None,
+        rooms: Vec::new(),
+        unplaced_doors: Vec::new()
+    });
+    areas.last_mut().unwrap()
+}
+
+// older area files are brought up to the current version before they are read
+fn parse_area(contents: &str) -> Result<AreaDefinition, String> {
+    let mut area: toml::Table = toml::from_str(contents).map_err(|error| error.to_string())?;
+    let version = area.get("version")
+        .ok_or_else(|| String::from("The area file has no version"))?
+        .as_integer()
+        .ok_or_else(|| String::from("The version must be a number"))?;
+    if version < 1 || version > i64::from(WORLD_VERSION) {
+        return Err(format!("Unsupported world version: {}", version));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(&mut area)?;
+    }
+    area.insert(String::from("version"), toml::Value::from(WORLD_VERSION as i64));
+    toml::Value::Table(area).try_into().map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(doorid: usize, destination: &str, isoneway: bool, linkeddoor: Option<i32>) -> Option<DoorRecord> {
+        Some(DoorRecord {
+            doorid,
+            destination: String::from(destination),
+            exitaddr1: format!("{:X}", doorid * 8),
+            exitaddr2: format!("{:X}", doorid * 8 + 4),
+            isoneway,
+            linkeddoor
+        })
+    }
+
+    #[test]
+    fn csv_data_survives_conversion() {
+        let door_table: DoorTable = vec![
+            record(0, "65 00 03 07", true, None),
+            record(1, "C9 00 01 01", false, Some(2)),
+            record(2, "65 00 05 07", false, Some(1)),
+            record(3, "C9 00 02 02", true, None)
+        ];
+        let destination = |id: usize| door_table[id].as_ref().unwrap().extract_destination();
+        let exit = |id: usize| door_table[id].as_ref().unwrap().extract_exit();
+        let rooms = vec![
+            Room::new(0, vec![], vec![], vec![exit(0)], vec![]).unwrap(),
+            Room::new(1, vec![destination(0)], vec![destination(2)], vec![], vec![exit(1)]).unwrap(),
+            Room::new(2, vec![], vec![destination(1)], vec![], vec![exit(2)]).unwrap()
+        ];
+        // a room can only lead into one room of the game
+        assert!(Room::new(3, vec![destination(0)], vec![destination(1)], vec![], vec![]).is_err());
+
+        let world = World::from_csv(&door_table, &rooms);
+        assert_eq!(world.areas.iter().map(|area| area.area).collect::<Vec<u16>>(), vec![0, 1, 2]);
+        assert_eq!(world.areas[2].unplaced_doors[0].id, 3);
+
+        let contents: Vec<String> = world.areas.iter().map(|area| toml::to_string(area).unwrap()).collect();
+        let loaded = World {
+            areas: contents.iter().map(|contents| toml::from_str(contents).unwrap()).collect()
+        };
+        assert_eq!(loaded, world);
+
+        let loaded_door_table = loaded.door_table();
+        for (loaded_record, record) in loaded_door_table.iter().zip(door_table.iter()) {
+            let (loaded_record, record) = (loaded_record.as_ref().unwrap(), record.as_ref().unwrap());
+            assert_eq!(loaded_record.extract_destination().destination_bytes, record.extract_destination().destination_bytes);
+            assert_eq!(loaded_record.extract_exit(), record.extract_exit());
+            assert_eq!(loaded_record.extract_exit().linked_door_id, record.extract_exit().linked_door_id);
+        }
+        let loaded_rooms = loaded.rooms(&loaded_door_table).unwrap();
+        assert_eq!(loaded_rooms.len(), 3);
+        assert_eq!(loaded_rooms[1].game_room, Some(101));
+        assert_eq!(loaded_rooms[1].two_way_exits, rooms[1].two_way_exits);
+        assert_eq!(loaded_rooms[1].one_way_entrances, rooms[1].one_way_entrances);
+        assert_eq!(loaded_rooms[1].two_way_entrances, rooms[1].two_way_entrances);
+
+        // internal edges have to lead from an entrance of the room to one of its doors
+        let mut with_edges = loaded.clone();
+        with_edges.areas[1].rooms[0].internal_edges.push(InternalEdge { from: 0, to: 1, requirements: vec![String::from("hammer")] });
+        assert!(with_edges.rooms(&loaded_door_table).is_ok());
+        with_edges.areas[1].rooms[0].internal_edges.push(InternalEdge { from: 1, to: 1, requirements: vec![] });
+        assert!(with_edges.rooms(&loaded_door_table).is_err());
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let area = parse_area(&format!("version = {}\narea = 1\n\n[[rooms]]\nid = 1\nentrances = [0, 2]\n", WORLD_VERSION)).unwrap();
+        assert_eq!(area.rooms[0].entrances, vec![0, 2]);
+
+        assert!(parse_area("area = 1\n").is_err());
+        assert!(parse_area(&format!("version = {}\narea = 1\n", WORLD_VERSION + 1)).is_err());
+    }
+}